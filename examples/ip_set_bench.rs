@@ -0,0 +1,50 @@
+use std::time::Instant;
+
+use rust_util::util_net::{IpAddress, IpAddressMask, IpAndIpMaskMatcher};
+use rust_util::util_net_frozen::FrozenIpSet;
+
+const ENTRY_COUNT: usize = 1_000_000;
+const LOOKUP_COUNT: usize = 1_000_000;
+
+// cargo run --release --example ip_set_bench
+fn main() {
+    let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next_u32 = || {
+        // xorshift, good enough for benchmark data
+        seed ^= seed << 13;
+        seed ^= seed >> 7;
+        seed ^= seed << 17;
+        (seed >> 32) as u32
+    };
+    let ip_address_masks = (0..ENTRY_COUNT).map(|i| {
+        let ipv4 = next_u32().to_be_bytes();
+        IpAddressMask::Ipv4(ipv4, [32, 28, 24][i % 3])
+    }).collect::<Vec<_>>();
+    let lookups = (0..LOOKUP_COUNT).map(|_| IpAddress::Ipv4(next_u32().to_be_bytes())).collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let mut matcher = IpAndIpMaskMatcher::new();
+    for ip_address_mask in &ip_address_masks {
+        if let IpAddressMask::Ipv4(ipv4, mask_len) = ip_address_mask {
+            matcher.add_ip_address_mask(&IpAddress::Ipv4(*ipv4), *mask_len);
+        }
+    }
+    println!("IpAndIpMaskMatcher build: {:?}", start.elapsed());
+
+    let start = Instant::now();
+    let frozen_ip_set = FrozenIpSet::from_ip_address_masks(&ip_address_masks);
+    println!("FrozenIpSet build: {:?}, intervals: {}, bytes: {}", start.elapsed(), frozen_ip_set.len(), frozen_ip_set.as_bytes().len());
+
+    let start = Instant::now();
+    let matched = lookups.iter().filter(|ip_address| matcher.contains_ip_address(ip_address)).count();
+    println!("IpAndIpMaskMatcher {} lookups: {:?}, matched: {}", LOOKUP_COUNT, start.elapsed(), matched);
+
+    let start = Instant::now();
+    let frozen_matched = lookups.iter().filter(|ip_address| frozen_ip_set.contains_ip_address(ip_address)).count();
+    println!("FrozenIpSet {} lookups: {:?}, matched: {}", LOOKUP_COUNT, start.elapsed(), frozen_matched);
+    assert_eq!(matched, frozen_matched);
+
+    let start = Instant::now();
+    let loaded = FrozenIpSet::from_bytes(frozen_ip_set.as_bytes().to_vec()).unwrap();
+    println!("FrozenIpSet load: {:?}, intervals: {}", start.elapsed(), loaded.len());
+}
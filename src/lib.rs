@@ -1,4 +1,6 @@
 #[macro_use] extern crate lazy_static;
+// make `rust_util::` paths in exported macros work inside this crate
+extern crate self as rust_util;
 extern crate term;
 
 use std::error::Error;
@@ -15,12 +17,34 @@ pub mod util_size;
 pub mod util_file;
 pub mod util_time;
 pub mod util_net;
+pub mod util_net_prefix_map;
+pub mod util_net_cidr;
+pub mod util_net_acl;
+pub mod util_net_subnet;
+pub mod util_net_special;
+pub mod util_net_loader;
+pub mod util_net_host;
+pub mod util_net_forwarded;
+pub mod util_net_port;
+pub mod util_net_relay;
+pub mod util_net_interface;
+pub mod util_net_rate_limit;
+pub mod util_net_frozen;
+pub mod util_net_resolv;
 pub mod util_term;
 pub mod util_git;
 #[cfg(feature = "use_clap")]
 pub mod util_clap;
 pub mod util_tlv;
+pub mod util_proxy_protocol;
+pub mod util_mmdb;
 pub mod util_runtime;
+pub mod util_exit;
+pub mod util_err;
+pub mod util_retry;
+pub mod util_panic;
+
+pub use util_exit::run_main;
 
 /// iff!(condition, result_when_true, result_when_false)
 #[macro_export] macro_rules! iff {
@@ -53,9 +77,9 @@ pub mod util_runtime;
 }
 #[macro_export] macro_rules! failure_and_exit {
     ($($arg:tt)+) => ( {
-        rust_util::util_msg::print_error(&format!($($arg)+));
+        rust_util::util_err::print_failure(&format!($($arg)+), Some((file!(), line!())), rust_util::util_exit::EXIT_SOFTWARE);
         rust_util::util_runtime::invoke_callbacks();
-        std::process::exit(-1);
+        std::process::exit(rust_util::util_exit::EXIT_SOFTWARE);
     } )
 }
 #[macro_export] macro_rules! opt_value {
@@ -64,10 +88,13 @@ pub mod util_runtime;
 
 pub type XResult<T> = Result<T, Box<dyn Error>>;
 
+// `io::Error::other` requires Rust 1.74
+#[allow(clippy::io_other_error)]
 pub fn new_box_error(m: &str) -> Box<dyn Error> {
     Box::new(IoError::new(ErrorKind::Other, m))
 }
 
+#[allow(clippy::io_other_error)]
 pub fn new_box_ioerror(m: &str) -> Box<dyn Error> {
     Box::new(IoError::new(ErrorKind::Other, m))
 }
@@ -76,9 +103,7 @@ pub fn new_box_ioerror(m: &str) -> Box<dyn Error> {
     ($ex: expr, $($arg:tt)+) => (
         match $ex {
             Some(o) => o,
-            None => return Err(rust_util::SimpleError::new(
-                format!("{}, file: {}, line: {}", format!($($arg)+), file!(), line!())
-            ).into()),
+            None => return Err(rust_util::SimpleError::new(format!($($arg)+)).with_location(file!(), line!()).into()),
         }
     )
 }
@@ -87,42 +112,147 @@ pub fn new_box_ioerror(m: &str) -> Box<dyn Error> {
     ($ex: expr, $($arg:tt)+) => (
         match $ex {
             Ok(o) => o,
-            Err(e) => return Err(rust_util::SimpleError::new(
-                format!("{}, file: {}, line: {}", format!($($arg)+, e), file!(), line!())
-            ).into()),
+            Err(e) => return Err(rust_util::SimpleError::new(format!($($arg)+, e)).with_location(file!(), line!()).into()),
         }
     )
 }
 #[macro_export] macro_rules! simple_error {
-    ($($arg:tt)+) => ( Err(rust_util::SimpleError::new(
-        format!("{}, file: {}, line: {}", format!($($arg)+), file!(), line!())
-    ).into()) )
+    ($($arg:tt)+) => ( Err(rust_util::SimpleError::new(format!($($arg)+)).with_location(file!(), line!()).into()) )
+}
+
+#[macro_export] macro_rules! bail {
+    ($($arg:tt)+) => ( return rust_util::simple_error!($($arg)+) )
+}
+#[macro_export] macro_rules! ensure {
+    ($cond: expr) => (
+        if !$cond {
+            rust_util::bail!("Condition failed: `{}`", stringify!($cond));
+        }
+    );
+    ($cond: expr, $($arg:tt)+) => (
+        if !$cond {
+            rust_util::bail!("{}, condition failed: `{}`", format!($($arg)+), stringify!($cond));
+        }
+    )
+}
+#[macro_export] macro_rules! ensure_eq {
+    ($left: expr, $right: expr) => (
+        match (&$left, &$right) {
+            (left, right) => if !(*left == *right) {
+                rust_util::bail!("Condition failed: `{} == {}`, left: {:?}, right: {:?}", stringify!($left), stringify!($right), left, right);
+            }
+        }
+    );
+    ($left: expr, $right: expr, $($arg:tt)+) => (
+        match (&$left, &$right) {
+            (left, right) => if !(*left == *right) {
+                rust_util::bail!("{}, condition failed: `{} == {}`, left: {:?}, right: {:?}", format!($($arg)+), stringify!($left), stringify!($right), left, right);
+            }
+        }
+    )
+}
+/// Same as `ensure!`, only checked when `debug_assertions` is on
+#[macro_export] macro_rules! debug_ensure {
+    ($($arg:tt)+) => ( if cfg!(debug_assertions) { rust_util::ensure!($($arg)+); } )
 }
 
 #[derive(Debug)]
 pub struct SimpleError {
     pub message: String,
     pub source: Option<Box<dyn Error>>,
+    location: Option<(&'static str, u32)>,
+    help: Option<String>,
+    context: Vec<(String, String)>,
 }
 
 impl SimpleError {
     pub fn new(message: String) -> Self {
-        Self { message, source: None }
+        Self { message, source: None, location: None, help: None, context: vec![] }
     }
 
     pub fn new2(message: String, source: Box<dyn Error>) -> Self {
-        Self { message, source: Some(source) }
+        Self { message, source: Some(source), location: None, help: None, context: vec![] }
+    }
+
+    pub fn with_location(mut self, file: &'static str, line: u32) -> Self {
+        self.location = Some((file, line));
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Self {
+        self.help = Some(help.to_string());
+        self
+    }
+
+    pub fn with_context(mut self, key: &str, value: &str) -> Self {
+        self.context.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn get_location(&self) -> Option<(&'static str, u32)> {
+        self.location
+    }
+
+    pub fn get_help(&self) -> Option<&str> {
+        self.help.as_deref()
+    }
+
+    pub fn get_context(&self) -> &[(String, String)] {
+        &self.context
     }
 }
 
+// `{}` shows message and location, `{:#}` also shows source error
 impl Display for SimpleError {
     fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}", self.message)?;
+        if let Some((file, line)) = &self.location {
+            write!(f, ", file: {}, line: {}", file, line)?;
+        }
         match &self.source {
-            None => write!(f, "SimpleErorr, message: {}", self.message),
-            Some(e) => write!(f, "SimpleErorr, message: {}, source erorr: {}", self.message, e),
+            Some(e) if f.alternate() => write!(f, ", source error: {}", e),
+            _ => Ok(()),
         }
     }
 }
 
-impl Error for SimpleError {}
+impl Error for SimpleError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.source.as_deref()
+    }
+}
+
+
+#[test]
+fn test_ensure() {
+    fn check(a: i32, b: i32) -> XResult<i32> {
+        ensure!(a > 0);
+        ensure!(b > 0, "b must be positive, b: {}", b);
+        ensure_eq!(a, b);
+        ensure_eq!(a + b, 2, "sum must be {}", 2);
+        debug_ensure!(a == 1, "a must be 1");
+        Ok(a + b)
+    }
+    assert_eq!(2, check(1, 1).unwrap());
+    let e = check(0, 1).unwrap_err();
+    assert_eq!("Condition failed: `a > 0`", e.downcast_ref::<SimpleError>().unwrap().message);
+    let e = check(1, -1).unwrap_err();
+    assert_eq!("b must be positive, b: -1, condition failed: `b > 0`", e.downcast_ref::<SimpleError>().unwrap().message);
+    let e = check(1, 2).unwrap_err();
+    assert_eq!("Condition failed: `a == b`, left: 1, right: 2", e.downcast_ref::<SimpleError>().unwrap().message);
+    let e = check(2, 2).unwrap_err();
+    assert_eq!("sum must be 2, condition failed: `a + b == 2`, left: 4, right: 2", e.downcast_ref::<SimpleError>().unwrap().message);
+    assert!(e.downcast_ref::<SimpleError>().unwrap().get_location().is_some());
+}
 
+#[test]
+fn test_bail() {
+    fn check(a: i32) -> XResult<()> {
+        if a < 0 {
+            bail!("negative: {}", a);
+        }
+        Ok(())
+    }
+    assert!(check(1).is_ok());
+    assert_eq!("negative: -1", check(-1).unwrap_err().downcast_ref::<SimpleError>().unwrap().message);
+}
@@ -89,7 +89,7 @@ impl CommandExecutor {
             None => {
                 util_msg::print_error("No default command, please try help (--help)");
                 crate::util_runtime::invoke_callbacks();
-                process::exit(1);
+                process::exit(crate::util_exit::EXIT_USAGE);
             }
             Some(default_cmd) => match default_cmd.run(&matches)? {
                 None => return Ok(()),
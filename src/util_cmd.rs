@@ -42,6 +42,8 @@ pub fn run_command_and_wait(cmd: &mut Command) -> io::Result<ExitStatus> {
     cmd.spawn()?.wait()
 }
 
+// `io::Error::other` requires Rust 1.74
+#[allow(clippy::io_other_error)]
 pub fn extract_package_and_wait(dir: &str, file_name: &str) -> io::Result<ExitStatus> {
     let mut cmd: Command;
     if file_name.ends_with(".zip") {
@@ -0,0 +1,282 @@
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Arc, RwLock};
+
+use crate::{util_env, util_msg, util_term, SimpleError};
+use crate::util_exit::{self, ExitCodeError};
+
+lazy_static! {
+    static ref ERROR_JSON: Arc<RwLock<bool>> = Arc::new(RwLock::new(util_env::is_env_on("ERROR_JSON")));
+}
+
+/// When on, errors from `failure_and_exit!` and `run_main` are printed as one JSON object to stderr,
+/// default value is from env `ERROR_JSON`
+pub fn set_error_json(is_error_json: bool) {
+    let mut error_json = ERROR_JSON.write().unwrap();
+    *error_json = is_error_json;
+}
+
+pub fn is_error_json() -> bool {
+    *ERROR_JSON.read().unwrap()
+}
+
+/// Multi-line error report:
+/// ```text
+/// error: <message>
+///   at <file>:<line>
+///
+/// Caused by:
+///     0: <source message>
+///
+/// help: <help>
+/// ```
+pub struct ErrorReport<'a> {
+    error: &'a (dyn Error + 'static),
+    color: bool,
+}
+
+impl<'a> ErrorReport<'a> {
+    pub fn new(error: &'a (dyn Error + 'static)) -> Self {
+        Self { error, color: *util_msg::IS_ATTY }
+    }
+
+    pub fn with_color(mut self, color: bool) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn headline(&self) -> String {
+        get_error_message(self.error)
+    }
+
+    pub fn render(&self) -> String {
+        let mut lines = vec![];
+        lines.push(format!("{}: {}", self.paint(util_term::RED, "error"), self.paint(util_term::BOLD, &self.headline())));
+        if let Some((file, line)) = get_error_location(self.error) {
+            lines.push(format!("  at {}:{}", file, line));
+        }
+        lines.extend(self.render_details());
+        lines.join("\n")
+    }
+
+    /// Lines after headline and its location
+    pub fn render_details(&self) -> Vec<String> {
+        let mut lines = vec![];
+        let causes = get_causes(self.error);
+        if !causes.is_empty() {
+            lines.push(String::new());
+            lines.push(self.paint(util_term::BOLD, "Caused by:"));
+            for (i, cause) in causes.iter().enumerate() {
+                lines.push(format!("    {}: {}", i, get_error_message(*cause)));
+                if let Some((file, line)) = get_error_location(*cause) {
+                    lines.push(format!("       at {}:{}", file, line));
+                }
+            }
+        }
+        for help in get_helps(self.error) {
+            lines.push(String::new());
+            lines.push(format!("{}: {}", self.paint(util_term::YELLOW, "help"), help));
+        }
+        lines
+    }
+
+    /// Render as JSON object:
+    /// `{"message":"..","location":{"file":"..","line":1},"exit_code":1,"causes":[{"message":"..","location":null}],"context":{"k":"v"},"help":[".."]}`
+    pub fn to_json(&self) -> String {
+        let mut json = String::with_capacity(256);
+        json.push_str("{\"message\":");
+        json.push_str(&json_string(&self.headline()));
+        json.push_str(",\"location\":");
+        json.push_str(&json_location(get_error_location(self.error)));
+        json.push_str(&format!(",\"exit_code\":{}", util_exit::get_exit_code(self.error)));
+        json.push_str(",\"causes\":[");
+        json.push_str(&get_causes(self.error).iter().map(|cause| {
+            format!("{{\"message\":{},\"location\":{}}}",
+                    json_string(&get_error_message(*cause)), json_location(get_error_location(*cause)))
+        }).collect::<Vec<_>>().join(","));
+        json.push_str("],\"context\":{");
+        json.push_str(&get_contexts(self.error).iter().map(|(k, v)| {
+            format!("{}:{}", json_string(k), json_string(v))
+        }).collect::<Vec<_>>().join(","));
+        json.push_str("},\"help\":[");
+        json.push_str(&get_helps(self.error).iter().map(|h| json_string(h)).collect::<Vec<_>>().join(","));
+        json.push_str("]}");
+        json
+    }
+
+    /// Print headline with `failure!` style then the details, or JSON when `is_error_json()`
+    pub fn print(&self) {
+        if is_error_json() {
+            eprintln!("{}", self.to_json());
+            return;
+        }
+        let mut headline = self.headline();
+        if let Some((file, line)) = get_error_location(self.error) {
+            headline.push_str(&format!(" (at {}:{})", file, line));
+        }
+        util_msg::print_error(&headline);
+        let details = self.render_details();
+        if !details.is_empty() && util_msg::is_logger_level_enabled(util_msg::MessageType::ERROR) {
+            util_msg::print_ex(&details.join("\n"), true);
+        }
+    }
+
+    fn paint(&self, color: &str, s: &str) -> String {
+        if self.color {
+            format!("{}{}{}", color, s, util_term::END)
+        } else {
+            s.to_string()
+        }
+    }
+}
+
+impl Display for ErrorReport<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+pub fn get_error_message(error: &(dyn Error + 'static)) -> String {
+    let error = unwrap_transparent(error);
+    match error.downcast_ref::<SimpleError>() {
+        Some(simple_error) => simple_error.message.clone(),
+        None => error.to_string(),
+    }
+}
+
+pub fn get_error_location(error: &(dyn Error + 'static)) -> Option<(&'static str, u32)> {
+    unwrap_transparent(error).downcast_ref::<SimpleError>().and_then(|e| e.get_location())
+}
+
+// ExitCodeError only carries exit code, message and location are from the wrapped error
+fn unwrap_transparent<'a>(error: &'a (dyn Error + 'static)) -> &'a (dyn Error + 'static) {
+    match error.downcast_ref::<ExitCodeError>() {
+        Some(exit_code_error) => exit_code_error.error.as_ref(),
+        None => error,
+    }
+}
+
+fn get_causes<'a>(error: &'a (dyn Error + 'static)) -> Vec<&'a (dyn Error + 'static)> {
+    let mut causes = vec![];
+    let mut current = error.source();
+    while let Some(e) = current {
+        causes.push(e);
+        current = e.source();
+    }
+    causes
+}
+
+fn get_contexts(error: &(dyn Error + 'static)) -> Vec<(String, String)> {
+    let mut contexts = vec![];
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(simple_error) = unwrap_transparent(e).downcast_ref::<SimpleError>() {
+            contexts.extend(simple_error.get_context().iter().cloned());
+        }
+        current = e.source();
+    }
+    contexts
+}
+
+fn get_helps(error: &(dyn Error + 'static)) -> Vec<String> {
+    let mut helps = vec![];
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(help) = unwrap_transparent(e).downcast_ref::<SimpleError>().and_then(|e| e.get_help()) {
+            helps.push(help.to_string());
+        }
+        current = e.source();
+    }
+    helps
+}
+
+/// Print message by `failure!`, or as JSON error object when `is_error_json()`
+pub fn print_failure(message: &str, location: Option<(&str, u32)>, exit_code: i32) {
+    if is_error_json() {
+        eprintln!("{}", failure_to_json(message, location, exit_code));
+    } else {
+        util_msg::print_error(message);
+    }
+}
+
+fn failure_to_json(message: &str, location: Option<(&str, u32)>, exit_code: i32) -> String {
+    format!("{{\"message\":{},\"location\":{},\"exit_code\":{},\"causes\":[],\"context\":{{}},\"help\":[]}}",
+            json_string(message), json_location(location), exit_code)
+}
+
+fn json_location(location: Option<(&str, u32)>) -> String {
+    match location {
+        None => "null".to_string(),
+        Some((file, line)) => format!("{{\"file\":{},\"line\":{}}}", json_string(file), line),
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut json = String::with_capacity(s.len() + 2);
+    json.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+#[test]
+fn test_error_report() {
+    let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "No such file");
+    let error = SimpleError::new2("Read config failed".into(), io_error.into())
+        .with_location("src/main.rs", 10)
+        .with_help("create config file first");
+    let report = ErrorReport::new(&error).with_color(false).render();
+    assert_eq!(r##"error: Read config failed
+  at src/main.rs:10
+
+Caused by:
+    0: No such file
+
+help: create config file first"##, report);
+}
+
+#[test]
+fn test_error_report_simple() {
+    let error = SimpleError::new("Something wrong".into());
+    assert_eq!("error: Something wrong", ErrorReport::new(&error).with_color(false).render());
+    assert_eq!("Something wrong", error.to_string());
+    let error = error.with_location("src/lib.rs", 1);
+    assert_eq!("Something wrong, file: src/lib.rs, line: 1", error.to_string());
+}
+
+#[test]
+fn test_error_report_exit_code_error() {
+    let error = ExitCodeError::new(crate::util_exit::EXIT_USAGE, SimpleError::new("Bad argument".into()).with_location("src/main.rs", 3).with_help("see --help").into());
+    assert_eq!(r##"error: Bad argument
+  at src/main.rs:3
+
+help: see --help"##, ErrorReport::new(&error).with_color(false).render());
+}
+
+#[test]
+fn test_error_report_json() {
+    let io_error = std::io::Error::new(std::io::ErrorKind::NotFound, "No \"such\" file");
+    let error = SimpleError::new2("Read config\tfailed".into(), io_error.into())
+        .with_location("src/main.rs", 10)
+        .with_context("file", "/etc/app.conf")
+        .with_help("create config file first");
+    let error = ExitCodeError::new(util_exit::EXIT_CONFIG, error.into());
+    assert_eq!(r##"{"message":"Read config\tfailed","location":{"file":"src/main.rs","line":10},"exit_code":78,"causes":[{"message":"No \"such\" file","location":null}],"context":{"file":"/etc/app.conf"},"help":["create config file first"]}"##,
+               ErrorReport::new(&error).to_json());
+}
+
+#[test]
+fn test_failure_to_json() {
+    assert_eq!(r##"{"message":"Bad \"input\"","location":{"file":"src/main.rs","line":7},"exit_code":70,"causes":[],"context":{},"help":[]}"##,
+               failure_to_json("Bad \"input\"", Some(("src/main.rs", 7)), util_exit::EXIT_SOFTWARE));
+}
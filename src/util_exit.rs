@@ -0,0 +1,202 @@
+use std::any::TypeId;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::process;
+use std::sync::Mutex;
+
+use crate::{util_runtime, XResult};
+use crate::util_err::ErrorReport;
+
+// exit codes follow sysexits.h
+pub const EXIT_SUCCESS: i32 = 0;
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_DATA_ERROR: i32 = 65;
+pub const EXIT_UNAVAILABLE: i32 = 69;
+pub const EXIT_SOFTWARE: i32 = 70;
+pub const EXIT_IO_ERROR: i32 = 74;
+pub const EXIT_TEMP_FAIL: i32 = 75;
+pub const EXIT_CONFIG: i32 = 78;
+
+/// Error types implementing this trait should be registered by `register_exit_code` to be found by `get_exit_code`
+pub trait ExitCode {
+    fn exit_code(&self) -> i32;
+}
+
+type ExitCodeGetter = fn(&(dyn Error + 'static)) -> Option<i32>;
+
+lazy_static! {
+    static ref EXIT_CODE_GETTERS: Mutex<Vec<(TypeId, ExitCodeGetter)>> = Mutex::new(vec![
+        (TypeId::of::<ExitCodeError>(), get_exit_code_of::<ExitCodeError>),
+        (TypeId::of::<io::Error>(), get_exit_code_of::<io::Error>),
+    ]);
+}
+
+/// Register error type `E`, so its exit code is used when it is in the error cause chain
+pub fn register_exit_code<E: Error + ExitCode + 'static>() {
+    let mut exit_code_getters = EXIT_CODE_GETTERS.lock().unwrap();
+    if !exit_code_getters.iter().any(|(type_id, _)| *type_id == TypeId::of::<E>()) {
+        exit_code_getters.push((TypeId::of::<E>(), get_exit_code_of::<E>));
+    }
+}
+
+fn get_exit_code_of<E: Error + ExitCode + 'static>(error: &(dyn Error + 'static)) -> Option<i32> {
+    error.downcast_ref::<E>().map(ExitCode::exit_code)
+}
+
+#[derive(Debug)]
+pub struct ExitCodeError {
+    pub code: i32,
+    pub error: Box<dyn Error>,
+}
+
+impl ExitCodeError {
+    pub fn new(code: i32, error: Box<dyn Error>) -> Self {
+        Self { code, error }
+    }
+
+    pub fn new_message(code: i32, message: &str) -> Self {
+        Self::new(code, crate::SimpleError::new(message.to_string()).into())
+    }
+
+    pub fn usage(message: &str) -> Self {
+        Self::new_message(EXIT_USAGE, message)
+    }
+
+    pub fn io(message: &str) -> Self {
+        Self::new_message(EXIT_IO_ERROR, message)
+    }
+
+    pub fn unavailable(message: &str) -> Self {
+        Self::new_message(EXIT_UNAVAILABLE, message)
+    }
+}
+
+impl Display for ExitCodeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)
+    }
+}
+
+// ExitCodeError is transparent, the wrapped error's source is returned
+impl Error for ExitCodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.error.source()
+    }
+}
+
+impl ExitCode for ExitCodeError {
+    fn exit_code(&self) -> i32 {
+        self.code
+    }
+}
+
+impl ExitCode for io::Error {
+    fn exit_code(&self) -> i32 {
+        EXIT_IO_ERROR
+    }
+}
+
+pub trait WithExitCode<T> {
+    fn with_exit_code(self, code: i32) -> XResult<T>;
+}
+
+impl<T> WithExitCode<T> for XResult<T> {
+    fn with_exit_code(self, code: i32) -> XResult<T> {
+        self.map_err(|e| ExitCodeError::new(code, e).into())
+    }
+}
+
+/// Find exit code from error and its cause chain by registered `ExitCode` types, the outermost one wins
+pub fn get_exit_code(error: &(dyn Error + 'static)) -> i32 {
+    let exit_code_getters = EXIT_CODE_GETTERS.lock().unwrap().iter().map(|(_, getter)| *getter).collect::<Vec<_>>();
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(code) = exit_code_getters.iter().find_map(|getter| getter(e)) {
+            return code;
+        }
+        current = e.source();
+    }
+    EXIT_FAILURE
+}
+
+pub fn get_error_chain(error: &(dyn Error + 'static)) -> Vec<String> {
+    let mut chain = vec![];
+    let mut current = Some(error);
+    while let Some(e) = current {
+        chain.push(e.to_string());
+        current = e.source();
+    }
+    chain
+}
+
+pub fn print_error_chain(error: &(dyn Error + 'static)) {
+    ErrorReport::new(error).print();
+}
+
+/// Run main function, on error print cause chain, invoke exit callbacks and exit with error's code
+pub fn run_main<F>(f: F) -> ! where F: FnOnce() -> XResult<()> {
+    let code = run_main_for_code(f);
+    process::exit(code);
+}
+
+pub fn run_main_for_code<F>(f: F) -> i32 where F: FnOnce() -> XResult<()> {
+    let code = match f() {
+        Ok(_) => EXIT_SUCCESS,
+        Err(e) => {
+            print_error_chain(e.as_ref());
+            get_exit_code(e.as_ref())
+        }
+    };
+    util_runtime::invoke_callbacks();
+    code
+}
+
+#[test]
+fn test_get_exit_code() {
+    let e: Box<dyn Error> = crate::SimpleError::new("test".into()).into();
+    assert_eq!(EXIT_FAILURE, get_exit_code(e.as_ref()));
+    let e: Box<dyn Error> = io::Error::new(io::ErrorKind::NotFound, "not found").into();
+    assert_eq!(EXIT_IO_ERROR, get_exit_code(e.as_ref()));
+    let e: Box<dyn Error> = ExitCodeError::usage("bad args").into();
+    assert_eq!(EXIT_USAGE, get_exit_code(e.as_ref()));
+    assert!(e.to_string().contains("bad args"));
+    let e: Box<dyn Error> = crate::SimpleError::new2("outer".into(), ExitCodeError::unavailable("remote").into()).into();
+    assert_eq!(EXIT_UNAVAILABLE, get_exit_code(e.as_ref()));
+}
+
+#[test]
+fn test_register_exit_code() {
+    #[derive(Debug)]
+    struct ConfigError;
+    impl Display for ConfigError {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "bad config")
+        }
+    }
+    impl Error for ConfigError {}
+    impl ExitCode for ConfigError {
+        fn exit_code(&self) -> i32 {
+            EXIT_CONFIG
+        }
+    }
+    let e: Box<dyn Error> = crate::SimpleError::new2("load failed".into(), ConfigError.into()).into();
+    assert_eq!(EXIT_FAILURE, get_exit_code(e.as_ref()));
+    register_exit_code::<ConfigError>();
+    assert_eq!(EXIT_CONFIG, get_exit_code(e.as_ref()));
+    // registering again changes nothing
+    register_exit_code::<ConfigError>();
+    assert_eq!(EXIT_CONFIG, get_exit_code(e.as_ref()));
+    let type_id = TypeId::of::<ConfigError>();
+    assert_eq!(1, EXIT_CODE_GETTERS.lock().unwrap().iter().filter(|(id, _)| *id == type_id).count());
+}
+
+#[test]
+fn test_with_exit_code() {
+    let r: XResult<()> = Err(crate::SimpleError::new("test".into()).into());
+    let e = r.with_exit_code(EXIT_CONFIG).unwrap_err();
+    assert_eq!(EXIT_CONFIG, get_exit_code(e.as_ref()));
+    assert_eq!(EXIT_SUCCESS, run_main_for_code(|| Ok(())));
+    assert_eq!(EXIT_TEMP_FAIL, run_main_for_code(|| Err(ExitCodeError::new_message(EXIT_TEMP_FAIL, "retry later").into())));
+}
@@ -98,19 +98,19 @@ pub fn read_to_bytes(read: &mut dyn Read) -> XResult<Vec<u8>> {
     Ok(buffer)
 }
 
-pub fn copy_io_default<R: ?Sized, W: ?Sized>(reader: &mut R, writer: &mut W, total: i64) -> io::Result<u64>
-    where R: io::Read, W: io::Write {
+pub fn copy_io_default<R, W>(reader: &mut R, writer: &mut W, total: i64) -> io::Result<u64>
+    where R: io::Read + ?Sized, W: io::Write + ?Sized {
     copy_io_with_head(reader, writer, total, "Downloading", &mut PrintStatusContext::default())
 }
 
-pub fn copy_io<R: ?Sized, W: ?Sized>(reader: &mut R, writer: &mut W, total: i64, print_status_context: &mut PrintStatusContext)
+pub fn copy_io<R, W>(reader: &mut R, writer: &mut W, total: i64, print_status_context: &mut PrintStatusContext)
                                      -> io::Result<u64>
-    where R: io::Read, W: io::Write {
+    where R: io::Read + ?Sized, W: io::Write + ?Sized {
     copy_io_with_head(reader, writer, total, "Downloading", print_status_context)
 }
 
-pub fn copy_io_with_head<R: ?Sized, W: ?Sized>(reader: &mut R, writer: &mut W, total: i64, head: &str, print_status_context: &mut PrintStatusContext) -> io::Result<u64>
-    where R: io::Read, W: io::Write {
+pub fn copy_io_with_head<R, W>(reader: &mut R, writer: &mut W, total: i64, head: &str, print_status_context: &mut PrintStatusContext) -> io::Result<u64>
+    where R: io::Read + ?Sized, W: io::Write + ?Sized {
     let written = copy_io_callback(reader, writer, total, print_status_context, &mut |total, written, _len, print_status_context| {
         print_status_last_line(head, total, written as i64, print_status_context);
     });
@@ -118,9 +118,9 @@ pub fn copy_io_with_head<R: ?Sized, W: ?Sized>(reader: &mut R, writer: &mut W, t
     written
 }
 
-pub fn copy_io_callback<R: ?Sized, W: ?Sized, FCallback>(reader: &mut R, writer: &mut W, total: i64, print_status_context: &mut PrintStatusContext, callback: &mut FCallback) -> io::Result<u64>
-    where R: io::Read,
-          W: io::Write,
+pub fn copy_io_callback<R, W, FCallback>(reader: &mut R, writer: &mut W, total: i64, print_status_context: &mut PrintStatusContext, callback: &mut FCallback) -> io::Result<u64>
+    where R: io::Read + ?Sized,
+          W: io::Write + ?Sized,
           FCallback: Fn(i64, u64, usize, &mut PrintStatusContext) {
     let mut written = 0u64;
     let mut buf: [u8; DEFAULT_BUF_SIZE] = [0u8; DEFAULT_BUF_SIZE];
@@ -0,0 +1,534 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::util_net::{IpAddress, IpAddressMask};
+use crate::{iff, opt_result, opt_value_result, simple_error, XResult};
+
+const METADATA_START_MARKER: &[u8] = b"\xab\xcd\xefMaxMind.com";
+// metadata is at most 128KiB at the end of file
+const METADATA_MAX_SIZE: usize = 128 * 1024;
+const DATA_SECTION_SEPARATOR_SIZE: usize = 16;
+const MAX_DECODE_DEPTH: usize = 512;
+
+/// Value decoded from MMDB data section
+#[derive(Debug, Clone, PartialEq)]
+pub enum MmdbValue {
+    String(String),
+    Double(f64),
+    Bytes(Vec<u8>),
+    Uint16(u16),
+    Uint32(u32),
+    Map(BTreeMap<String, MmdbValue>),
+    Int32(i32),
+    Uint64(u64),
+    Uint128(u128),
+    Array(Vec<MmdbValue>),
+    Boolean(bool),
+    Float(f32),
+}
+
+impl MmdbValue {
+    pub fn get(&self, key: &str) -> Option<&MmdbValue> {
+        match self {
+            MmdbValue::Map(map) => map.get(key),
+            MmdbValue::Array(array) => key.parse::<usize>().ok().and_then(|i| array.get(i)),
+            _ => None,
+        }
+    }
+
+    /// e.g. `value.get_path(&["country", "names", "en"])`, array index is number string
+    pub fn get_path(&self, path: &[&str]) -> Option<&MmdbValue> {
+        path.iter().try_fold(self, |value, key| value.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            MmdbValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match self {
+            MmdbValue::Uint16(n) => Some(*n as u64),
+            MmdbValue::Uint32(n) => Some(*n as u64),
+            MmdbValue::Uint64(n) => Some(*n),
+            MmdbValue::Uint128(n) => iff!(*n <= u64::MAX as u128, Some(*n as u64), None),
+            MmdbValue::Int32(n) => iff!(*n >= 0, Some(*n as u64), None),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            MmdbValue::Double(n) => Some(*n),
+            MmdbValue::Float(n) => Some(*n as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            MmdbValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MmdbMetadata {
+    pub node_count: u32,
+    // 24, 28 or 32
+    pub record_size: u16,
+    // 4 or 6
+    pub ip_version: u16,
+    pub database_type: String,
+    pub build_epoch: u64,
+    // all metadata fields
+    pub value: MmdbValue,
+}
+
+enum MmdbData {
+    Bytes(Vec<u8>),
+    #[cfg(unix)]
+    Mmap(Mmap),
+}
+
+impl MmdbData {
+    fn as_slice(&self) -> &[u8] {
+        match self {
+            MmdbData::Bytes(bytes) => bytes,
+            #[cfg(unix)]
+            MmdbData::Mmap(mmap) => mmap.as_slice(),
+        }
+    }
+}
+
+/// MaxMind DB reader, see: https://maxmind.github.io/MaxMind-DB/
+pub struct MmdbReader {
+    data: MmdbData,
+    pub metadata: MmdbMetadata,
+    data_section_start: usize,
+    data_section_end: usize,
+    // IPv4 addresses start at `::/96` in IPv6 database
+    ipv4_start_node: u32,
+}
+
+impl MmdbReader {
+    /// Read the whole file into memory
+    pub fn open<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        let path = path.as_ref();
+        let bytes = opt_result!(fs::read(path), "Read MMDB file: {}, failed: {}", path.display());
+        Self::from_bytes(bytes)
+    }
+
+    /// Memory map the file, the file should not be modified while it is mapped
+    #[cfg(unix)]
+    pub fn open_mmap<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        let path = path.as_ref();
+        let file = opt_result!(fs::File::open(path), "Open MMDB file: {}, failed: {}", path.display());
+        Self::from_data(MmdbData::Mmap(Mmap::map(&file)?))
+    }
+
+    pub fn from_bytes(bytes: Vec<u8>) -> XResult<Self> {
+        Self::from_data(MmdbData::Bytes(bytes))
+    }
+
+    fn from_data(data: MmdbData) -> XResult<Self> {
+        let buf = data.as_slice();
+        let search_start = buf.len().saturating_sub(METADATA_MAX_SIZE);
+        let marker_index = opt_value_result!(buf[search_start..].windows(METADATA_START_MARKER.len())
+            .rposition(|w| w == METADATA_START_MARKER), "MMDB metadata not found");
+        let metadata_start = search_start + marker_index + METADATA_START_MARKER.len();
+        let (metadata_value, _) = Decoder { data: &buf[metadata_start..] }.decode(0, 0)?;
+        let get_u64 = |key: &str| metadata_value.get(key).and_then(MmdbValue::as_u64);
+        let node_count = opt_value_result!(get_u64("node_count"), "MMDB metadata node_count not found");
+        let record_size = opt_value_result!(get_u64("record_size"), "MMDB metadata record_size not found");
+        let ip_version = opt_value_result!(get_u64("ip_version"), "MMDB metadata ip_version not found");
+        if ![24, 28, 32].contains(&record_size) {
+            return simple_error!("MMDB record size not supported: {}", record_size);
+        }
+        if ![4, 6].contains(&ip_version) || node_count > u32::MAX as u64 {
+            return simple_error!("MMDB metadata invalid, ip version: {}, node count: {}", ip_version, node_count);
+        }
+        let search_tree_size = node_count as usize * record_size as usize / 4;
+        let data_section_start = search_tree_size + DATA_SECTION_SEPARATOR_SIZE;
+        let data_section_end = metadata_start - METADATA_START_MARKER.len();
+        if data_section_start > data_section_end {
+            return simple_error!("MMDB search tree size: {} exceeds file size", search_tree_size);
+        }
+        let metadata = MmdbMetadata {
+            node_count: node_count as u32,
+            record_size: record_size as u16,
+            ip_version: ip_version as u16,
+            database_type: metadata_value.get("database_type").and_then(MmdbValue::as_str).unwrap_or("").to_string(),
+            build_epoch: get_u64("build_epoch").unwrap_or(0),
+            value: metadata_value.clone(),
+        };
+        let mut reader = Self { data, metadata, data_section_start, data_section_end, ipv4_start_node: 0 };
+        if reader.metadata.ip_version == 6 {
+            let mut node = 0;
+            for _ in 0..96 {
+                if node >= reader.metadata.node_count {
+                    break;
+                }
+                node = reader.read_record(node, 0)?;
+            }
+            reader.ipv4_start_node = node;
+        }
+        Ok(reader)
+    }
+
+    pub fn lookup(&self, ip_address: &IpAddress) -> XResult<Option<MmdbValue>> {
+        Ok(self.lookup_prefix(ip_address)?.map(|(value, _)| value))
+    }
+
+    /// Returns the value and the network which contains the address,
+    /// IPv4-mapped IPv6 address is looked up as IPv4 address
+    pub fn lookup_prefix(&self, ip_address: &IpAddress) -> XResult<Option<(MmdbValue, IpAddressMask)>> {
+        let ip_address = ip_address.to_canonical();
+        let (bits, bit_len, start_node) = match &ip_address {
+            IpAddress::Ipv4(ipv4) => ((u32::from_be_bytes(*ipv4) as u128) << 96, 32, self.ipv4_start_node),
+            IpAddress::Ipv6(ipv6) => {
+                if self.metadata.ip_version == 4 {
+                    return simple_error!("Lookup IPv6 address: {} in IPv4 MMDB", ip_address);
+                }
+                (u128::from_be_bytes(*ipv6), 128, 0)
+            }
+        };
+        let node_count = self.metadata.node_count;
+        let mut node = start_node;
+        let mut depth = 0_u8;
+        while depth < bit_len && node < node_count {
+            node = self.read_record(node, ((bits >> (127 - depth)) & 1) as u8)?;
+            depth += 1;
+        }
+        if node == node_count {
+            return Ok(None);
+        }
+        if node < node_count {
+            return simple_error!("MMDB search tree is invalid, node: {}", node);
+        }
+        let offset = opt_value_result!(((node - node_count) as usize).checked_sub(DATA_SECTION_SEPARATOR_SIZE),
+            "MMDB data pointer is invalid: {}", node);
+        let data = &self.data.as_slice()[self.data_section_start..self.data_section_end];
+        let (value, _) = Decoder { data }.decode(offset, 0)?;
+        let ip_address_mask = match ip_address {
+            IpAddress::Ipv4(ipv4) => IpAddressMask::Ipv4(ipv4, depth),
+            IpAddress::Ipv6(ipv6) => IpAddressMask::Ipv6(ipv6, depth),
+        };
+        Ok(Some((value, ip_address_mask.to_network_mask())))
+    }
+
+    fn read_record(&self, node: u32, bit: u8) -> XResult<u32> {
+        let buf = self.data.as_slice();
+        let record_size = self.metadata.record_size as usize;
+        let node_size = record_size / 4;
+        let base = node as usize * node_size;
+        let node_bytes = opt_value_result!(buf.get(base..base + node_size), "MMDB node: {} out of range", node);
+        let be_u32 = |bytes: &[u8]| bytes.iter().fold(0_u32, |n, b| (n << 8) | *b as u32);
+        Ok(match (record_size, bit) {
+            (24, 0) => be_u32(&node_bytes[0..3]),
+            (24, _) => be_u32(&node_bytes[3..6]),
+            // middle byte holds the high 4 bits of both records
+            (28, 0) => ((node_bytes[3] as u32 & 0xf0) << 20) | be_u32(&node_bytes[0..3]),
+            (28, _) => ((node_bytes[3] as u32 & 0x0f) << 24) | be_u32(&node_bytes[4..7]),
+            (_, 0) => be_u32(&node_bytes[0..4]),
+            (_, _) => be_u32(&node_bytes[4..8]),
+        })
+    }
+}
+
+struct Decoder<'a> {
+    // pointers are offsets relative to the start of `data`
+    data: &'a [u8],
+}
+
+impl Decoder<'_> {
+    // returns value and the offset after the value
+    fn decode(&self, offset: usize, depth: usize) -> XResult<(MmdbValue, usize)> {
+        if depth > MAX_DECODE_DEPTH {
+            return simple_error!("MMDB data nested too deep");
+        }
+        let control = self.read_byte(offset)?;
+        let mut offset = offset + 1;
+        let mut type_num = control >> 5;
+        if type_num == 1 {
+            let (pointer, next_offset) = self.decode_pointer(control, offset)?;
+            if self.read_byte(pointer)? >> 5 == 1 {
+                return simple_error!("MMDB pointer to pointer at: {}", pointer);
+            }
+            let (value, _) = self.decode(pointer, depth + 1)?;
+            return Ok((value, next_offset));
+        }
+        if type_num == 0 {
+            type_num = 7 + self.read_byte(offset)?;
+            offset += 1;
+        }
+        let (size, mut offset) = self.decode_size(control & 0x1f, offset)?;
+        let value = match type_num {
+            2 => MmdbValue::String(opt_result!(String::from_utf8(self.read_bytes(offset, size)?.to_vec()), "MMDB string invalid: {}")),
+            3 => MmdbValue::Double(f64::from_bits(self.read_uint(offset, size, 8)? as u64)),
+            4 => MmdbValue::Bytes(self.read_bytes(offset, size)?.to_vec()),
+            5 => MmdbValue::Uint16(self.read_uint(offset, size, 2)? as u16),
+            6 => MmdbValue::Uint32(self.read_uint(offset, size, 4)? as u32),
+            7 => {
+                let mut map = BTreeMap::new();
+                for _ in 0..size {
+                    let (key, next_offset) = self.decode(offset, depth + 1)?;
+                    let key = opt_value_result!(key.as_str(), "MMDB map key is not string: {:?}", key).to_string();
+                    let (value, next_offset) = self.decode(next_offset, depth + 1)?;
+                    map.insert(key, value);
+                    offset = next_offset;
+                }
+                return Ok((MmdbValue::Map(map), offset));
+            }
+            8 => MmdbValue::Int32(self.read_uint(offset, size, 4)? as u32 as i32),
+            9 => MmdbValue::Uint64(self.read_uint(offset, size, 8)? as u64),
+            10 => MmdbValue::Uint128(self.read_uint(offset, size, 16)?),
+            11 => {
+                let mut array = Vec::with_capacity(size.min(1024));
+                for _ in 0..size {
+                    let (value, next_offset) = self.decode(offset, depth + 1)?;
+                    array.push(value);
+                    offset = next_offset;
+                }
+                return Ok((MmdbValue::Array(array), offset));
+            }
+            // boolean value is stored in size
+            14 => return Ok((MmdbValue::Boolean(size != 0), offset)),
+            15 => MmdbValue::Float(f32::from_bits(self.read_uint(offset, size, 4)? as u32)),
+            _ => return simple_error!("MMDB data type: {} not supported, at: {}", type_num, offset),
+        };
+        Ok((value, offset + size))
+    }
+
+    fn decode_size(&self, size: u8, offset: usize) -> XResult<(usize, usize)> {
+        Ok(match size {
+            29 => (29 + self.read_uint(offset, 1, 1)? as usize, offset + 1),
+            30 => (285 + self.read_uint(offset, 2, 2)? as usize, offset + 2),
+            31 => (65821 + self.read_uint(offset, 3, 3)? as usize, offset + 3),
+            size => (size as usize, offset),
+        })
+    }
+
+    fn decode_pointer(&self, control: u8, offset: usize) -> XResult<(usize, usize)> {
+        let pointer_size = ((control >> 3) & 0x03) as usize + 1;
+        let value = (control & 0x07) as usize;
+        let n = self.read_uint(offset, pointer_size, 4)? as usize;
+        let pointer = match pointer_size {
+            1 => (value << 8) | n,
+            2 => ((value << 16) | n) + 2048,
+            3 => ((value << 24) | n) + 526336,
+            _ => n,
+        };
+        Ok((pointer, offset + pointer_size))
+    }
+
+    fn read_byte(&self, offset: usize) -> XResult<u8> {
+        Ok(opt_value_result!(self.data.get(offset), "MMDB data offset: {} out of range", offset).to_owned())
+    }
+
+    fn read_bytes(&self, offset: usize, size: usize) -> XResult<&[u8]> {
+        Ok(opt_value_result!(self.data.get(offset..offset + size), "MMDB data offset: {}, size: {} out of range", offset, size))
+    }
+
+    // big endian unsigned integer in `size` bytes, at most `max_size` bytes
+    fn read_uint(&self, offset: usize, size: usize, max_size: usize) -> XResult<u128> {
+        if size > max_size {
+            return simple_error!("MMDB integer size: {} exceeds: {}", size, max_size);
+        }
+        Ok(self.read_bytes(offset, size)?.iter().fold(0_u128, |n, b| (n << 8) | *b as u128))
+    }
+}
+
+#[cfg(unix)]
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// the mapping is read only
+#[cfg(unix)]
+unsafe impl Send for Mmap {}
+
+#[cfg(unix)]
+unsafe impl Sync for Mmap {}
+
+#[cfg(unix)]
+impl Mmap {
+    fn map(file: &fs::File) -> XResult<Self> {
+        use std::os::unix::io::AsRawFd;
+        let len = opt_result!(file.metadata(), "Get file metadata failed: {}").len() as usize;
+        if len == 0 {
+            return simple_error!("Memory map empty file");
+        }
+        let ptr = unsafe { libc::mmap(std::ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0) };
+        if ptr == libc::MAP_FAILED {
+            return simple_error!("Memory map file failed: {}", std::io::Error::last_os_error());
+        }
+        Ok(Self { ptr, len })
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+#[test]
+fn test_mmdb_reader() {
+    // minimal MMDB writer: (prefix bits, prefix length, data offset)
+    fn encode_control(buf: &mut Vec<u8>, type_num: u8, size: usize) {
+        let size_bits = iff!(size < 29, size as u8, 29);
+        if type_num <= 7 {
+            buf.push((type_num << 5) | size_bits);
+        } else {
+            buf.push(size_bits);
+            buf.push(type_num - 7);
+        }
+        if size >= 29 {
+            buf.push((size - 29) as u8);
+        }
+    }
+    fn encode_string(buf: &mut Vec<u8>, s: &str) {
+        encode_control(buf, 2, s.len());
+        buf.extend_from_slice(s.as_bytes());
+    }
+    fn encode_uint(buf: &mut Vec<u8>, type_num: u8, n: u128) {
+        let bytes = n.to_be_bytes();
+        let bytes = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or(16)..];
+        encode_control(buf, type_num, bytes.len());
+        buf.extend_from_slice(bytes);
+    }
+    fn build_mmdb(ip_version: u16, record_size: u16, networks: &[(u128, u8, usize)], data: &[u8]) -> Vec<u8> {
+        // records: None is empty, Some(Err(offset)) is data, Some(Ok(node)) is node
+        let mut nodes: Vec<[Option<Result<usize, usize>>; 2]> = vec![[None, None]];
+        for (bits, prefix_len, offset) in networks {
+            let mut node = 0;
+            for depth in 0..*prefix_len {
+                let bit = ((bits >> (127 - depth)) & 1) as usize;
+                if depth + 1 == *prefix_len {
+                    nodes[node][bit] = Some(Err(*offset));
+                } else {
+                    node = match nodes[node][bit] {
+                        Some(Ok(next_node)) => next_node,
+                        _ => {
+                            nodes.push([None, None]);
+                            nodes[node][bit] = Some(Ok(nodes.len() - 1));
+                            nodes.len() - 1
+                        }
+                    };
+                }
+            }
+        }
+        let node_count = nodes.len();
+        let mut buf = vec![];
+        for records in &nodes {
+            let [left, right] = records.map(|r| match r {
+                None => node_count as u32,
+                Some(Ok(node)) => node as u32,
+                Some(Err(offset)) => (node_count + 16 + offset) as u32,
+            });
+            match record_size {
+                24 => {
+                    buf.extend_from_slice(&left.to_be_bytes()[1..]);
+                    buf.extend_from_slice(&right.to_be_bytes()[1..]);
+                }
+                28 => {
+                    buf.extend_from_slice(&left.to_be_bytes()[1..]);
+                    buf.push((((left >> 24) & 0x0f) << 4) as u8 | ((right >> 24) & 0x0f) as u8);
+                    buf.extend_from_slice(&right.to_be_bytes()[1..]);
+                }
+                _ => {
+                    buf.extend_from_slice(&left.to_be_bytes());
+                    buf.extend_from_slice(&right.to_be_bytes());
+                }
+            }
+        }
+        buf.extend_from_slice(&[0; 16]);
+        buf.extend_from_slice(data);
+        buf.extend_from_slice(METADATA_START_MARKER);
+        encode_control(&mut buf, 7, 4);
+        encode_string(&mut buf, "node_count");
+        encode_uint(&mut buf, 6, node_count as u128);
+        encode_string(&mut buf, "record_size");
+        encode_uint(&mut buf, 5, record_size as u128);
+        encode_string(&mut buf, "ip_version");
+        encode_uint(&mut buf, 5, ip_version as u128);
+        encode_string(&mut buf, "database_type");
+        encode_string(&mut buf, "Test-DB");
+        buf
+    }
+
+    // data section: {"country": "CN"} at 0, {"asn": 13335, "tags": [true, 1.5, -1], "country": <pointer to "CN">} at 12
+    let mut data = vec![];
+    encode_control(&mut data, 7, 1);
+    encode_string(&mut data, "country");
+    encode_string(&mut data, "CN");
+    let cn_offset = data.len() - 3;
+    let second_offset = data.len();
+    encode_control(&mut data, 7, 3);
+    encode_string(&mut data, "asn");
+    encode_uint(&mut data, 6, 13335);
+    encode_string(&mut data, "tags");
+    encode_control(&mut data, 11, 3);
+    encode_control(&mut data, 14, 1);
+    encode_control(&mut data, 3, 8);
+    data.extend_from_slice(&1.5_f64.to_bits().to_be_bytes());
+    encode_control(&mut data, 8, 4);
+    data.extend_from_slice(&(-1_i32).to_be_bytes());
+    encode_string(&mut data, "country");
+    data.push((1 << 5) | ((cn_offset >> 8) as u8 & 0x07));
+    data.push(cn_offset as u8);
+
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    for record_size in [24, 28, 32] {
+        let networks = [
+            ((10_u128 << 120), 8, 0),
+            ((u32::from_be_bytes([1, 1, 1, 0]) as u128) << 96, 24, second_offset),
+        ];
+        let reader = MmdbReader::from_bytes(build_mmdb(4, record_size, &networks, &data)).unwrap();
+        assert_eq!("Test-DB", reader.metadata.database_type);
+        let (value, network) = reader.lookup_prefix(&ip("10.1.2.3")).unwrap().unwrap();
+        assert_eq!(Some("CN"), value.get("country").and_then(MmdbValue::as_str));
+        assert_eq!("10.0.0.0/8", network.to_string());
+        let value = reader.lookup(&ip("::ffff:1.1.1.1")).unwrap().unwrap();
+        assert_eq!(Some(13335), value.get("asn").and_then(MmdbValue::as_u64));
+        assert_eq!(Some("CN"), value.get("country").and_then(MmdbValue::as_str));
+        assert_eq!(Some(true), value.get_path(&["tags", "0"]).and_then(MmdbValue::as_bool));
+        assert_eq!(Some(1.5), value.get_path(&["tags", "1"]).and_then(MmdbValue::as_f64));
+        assert_eq!(Some(&MmdbValue::Int32(-1)), value.get_path(&["tags", "2"]));
+        assert_eq!(None, reader.lookup(&ip("8.8.8.8")).unwrap());
+        assert!(reader.lookup(&ip("2001:db8::1")).is_err());
+    }
+
+    // IPv6 database with IPv4 subtree at `::/96`
+    let networks = [
+        ((10_u128 << 24), 96 + 8, 0),
+        (0x2001_0db8_u128 << 96, 32, second_offset),
+    ];
+    let bytes = build_mmdb(6, 28, &networks, &data);
+    let file = std::env::temp_dir().join(format!("rust_util_test_{}.mmdb", std::process::id()));
+    fs::write(&file, &bytes).unwrap();
+    for reader in [MmdbReader::open(&file).unwrap(), MmdbReader::open_mmap(&file).unwrap()] {
+        let (value, network) = reader.lookup_prefix(&ip("10.9.9.9")).unwrap().unwrap();
+        assert_eq!(Some("CN"), value.get("country").and_then(MmdbValue::as_str));
+        assert_eq!("10.0.0.0/8", network.to_string());
+        let (value, network) = reader.lookup_prefix(&ip("2001:db8::1")).unwrap().unwrap();
+        assert_eq!(Some(13335), value.get("asn").and_then(MmdbValue::as_u64));
+        assert_eq!("2001:db8::/32", network.to_string());
+        assert_eq!(None, reader.lookup(&ip("2001:db9::1")).unwrap());
+    }
+    fs::remove_file(&file).ok();
+
+    assert!(MmdbReader::from_bytes(b"not a mmdb".to_vec()).is_err());
+    assert!(MmdbReader::from_bytes(build_mmdb(4, 20, &[], &data)).is_err());
+}
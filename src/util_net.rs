@@ -1,14 +1,62 @@
+use std::convert::TryFrom;
+use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::result::Result;
-use std::net::SocketAddr;
-use crate::XResult;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::str::FromStr;
+use crate::{iff, XResult};
+use crate::util_net_cidr::IpRange;
 use std::collections::HashSet;
 
 const DEFAULT_LISTEN_ADDR: [u8; 4] = [127, 0, 0, 1];
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpAddressErrorKind {
+    InvalidIpv4,
+    InvalidIpv6,
+    InvalidMask,
+    InvalidPort,
+    InvalidFormat,
+    InvalidRange,
+    InvalidHostname,
+    NotIpv4,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpAddressError {
+    pub kind: IpAddressErrorKind,
+    pub input: String,
+}
+
+impl IpAddressError {
+    pub fn new(kind: IpAddressErrorKind, input: &str) -> Self {
+        Self { kind, input: input.to_string() }
+    }
+}
+
+impl Display for IpAddressError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        let message = match self.kind {
+            IpAddressErrorKind::InvalidIpv4 => "Invalid IPv4 address",
+            IpAddressErrorKind::InvalidIpv6 => "Invalid IPv6 address",
+            IpAddressErrorKind::InvalidMask => "Invalid mask",
+            IpAddressErrorKind::InvalidPort => "Invalid port",
+            IpAddressErrorKind::InvalidFormat => "Invalid format",
+            IpAddressErrorKind::InvalidRange => "Invalid range",
+            IpAddressErrorKind::InvalidHostname => "Invalid hostname",
+            IpAddressErrorKind::NotIpv4 => "Not IPv4 address",
+        };
+        write!(f, "{}: {}", message, self.input)
+    }
+}
+
+impl Error for IpAddressError {}
+
 pub struct IpAndIpMaskMatcher {
     min_mask_len: u8,
     ip_and_ip_mask_set: HashSet<u64>,
+    ipv6_min_mask_len: u8,
+    ipv6_and_ipv6_mask_set: HashSet<(u8, u128)>,
 }
 
 impl Default for IpAndIpMaskMatcher {
@@ -22,41 +70,69 @@ impl IpAndIpMaskMatcher {
         IpAndIpMaskMatcher {
             min_mask_len: 32,
             ip_and_ip_mask_set: HashSet::with_capacity(128),
+            ipv6_min_mask_len: 128,
+            ipv6_and_ipv6_mask_set: HashSet::new(),
         }
     }
 
+    // IPv4-mapped IPv6 network with mask length at least 96 is added as IPv4 network
     pub fn add_ip_address_mask(&mut self, ip_address: &IpAddress, mask_len: u8) -> bool {
-        if mask_len > 32 {
+        if mask_len > ip_address.max_mask_len() {
             return false;
         }
-        if mask_len < self.min_mask_len {
-            self.min_mask_len = mask_len;
+        let (ip_address, mask_len) = canonicalize_ip_address_mask(ip_address, mask_len);
+        match &ip_address {
+            IpAddress::Ipv4(ipv4) => {
+                if mask_len < self.min_mask_len {
+                    self.min_mask_len = mask_len;
+                }
+                let mask_n_ip = Self::get_mask_n_ip(ipv4_to_u32(ipv4) as u64, mask_len);
+                self.ip_and_ip_mask_set.insert(mask_n_ip);
+            }
+            IpAddress::Ipv6(ipv6) => {
+                if mask_len < self.ipv6_min_mask_len {
+                    self.ipv6_min_mask_len = mask_len;
+                }
+                self.ipv6_and_ipv6_mask_set.insert((mask_len, ipv6_to_u128(ipv6) & ipv6_mask(mask_len)));
+            }
         }
-        let mask_n_ip = Self::get_mask_n_ip(ip_address.to_u32() as u64, mask_len);
-        self.ip_and_ip_mask_set.insert(mask_n_ip);
         true
     }
 
     pub fn add_ip_address(&mut self, ip_address: &IpAddress) -> bool {
-        self.add_ip_address_mask(ip_address, 32)
+        self.add_ip_address_mask(ip_address, ip_address.max_mask_len())
     }
 
+    // IPv4-mapped IPv6 address is matched as IPv4 address
     pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
-        self.contains_ip_address_mask(ip_address, 32)
+        self.contains_ip_address_mask(ip_address, ip_address.max_mask_len())
     }
 
+    // IPv4-mapped IPv6 network with mask length at least 96 is matched as IPv4 network
     pub fn contains_ip_address_mask(&self, ip_address: &IpAddress, mask_len: u8) -> bool {
-        if mask_len > 32 {
+        if mask_len > ip_address.max_mask_len() {
             return false;
         }
-        let ip_addr_as_u64 = ip_address.to_u32() as u64;
-        for i_mask_len in (self.min_mask_len..=mask_len).rev() {
-            let mask_n_ip = Self::get_mask_n_ip(ip_addr_as_u64, i_mask_len);
-            if self.ip_and_ip_mask_set.contains(&mask_n_ip) {
-                return true;
+        let (ip_address, mask_len) = canonicalize_ip_address_mask(ip_address, mask_len);
+        match &ip_address {
+            IpAddress::Ipv4(ipv4) => {
+                let ip_addr_as_u64 = ipv4_to_u32(ipv4) as u64;
+                for i_mask_len in (self.min_mask_len..=mask_len).rev() {
+                    let mask_n_ip = Self::get_mask_n_ip(ip_addr_as_u64, i_mask_len);
+                    if self.ip_and_ip_mask_set.contains(&mask_n_ip) {
+                        return true;
+                    }
+                }
+                // IPv6 networks shorter than 96 may cover the whole IPv4-mapped range `::ffff:0:0/96`
+                self.contains_ipv6_value(ipv6_to_u128(&ipv4_to_ipv4_mapped(ipv4)), 95)
             }
+            IpAddress::Ipv6(ipv6) => self.contains_ipv6_value(ipv6_to_u128(ipv6), mask_len),
         }
-        false
+    }
+
+    fn contains_ipv6_value(&self, ip_addr_as_u128: u128, mask_len: u8) -> bool {
+        (self.ipv6_min_mask_len..=mask_len).rev()
+            .any(|i_mask_len| self.ipv6_and_ipv6_mask_set.contains(&(i_mask_len, ip_addr_as_u128 & ipv6_mask(i_mask_len))))
     }
 
     fn get_mask_n_ip(ip_addr_as_u64: u64, mask_len: u8) -> u64 {
@@ -66,9 +142,10 @@ impl IpAndIpMaskMatcher {
     }
 }
 
-#[derive(Debug, Clone, PartialOrd, PartialEq, Ord, Eq)]
+#[derive(Debug, Clone, PartialOrd, PartialEq, Ord, Eq, Hash)]
 pub enum IpAddress {
     Ipv4([u8; 4]),
+    Ipv6([u8; 16]),
 }
 
 impl IpAddress {
@@ -76,25 +153,77 @@ impl IpAddress {
         parse_ipv4_addr(addr).map(IpAddress::Ipv4)
     }
 
+    // supports `::` compression and IPv4-mapped address e.g. `::ffff:1.2.3.4`
+    pub fn parse_ipv6(addr: &str) -> Option<Self> {
+        parse_ipv6_addr(addr).map(IpAddress::Ipv6)
+    }
+
+    pub fn parse(addr: &str) -> Option<Self> {
+        addr.parse().ok()
+    }
+
+    pub fn is_ipv4(&self) -> bool {
+        matches!(self, IpAddress::Ipv4(_))
+    }
+
+    pub fn is_ipv6(&self) -> bool {
+        matches!(self, IpAddress::Ipv6(_))
+    }
+
+    // 32 for IPv4, 128 for IPv6
+    pub fn max_mask_len(&self) -> u8 {
+        match self {
+            IpAddress::Ipv4(_) => 32,
+            IpAddress::Ipv6(_) => 128,
+        }
+    }
+
     pub fn to_address(&self) -> String {
         match self {
             IpAddress::Ipv4(ipv4) => ipv4.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("."),
+            IpAddress::Ipv6(ipv6) => Ipv6Addr::from(*ipv6).to_string(),
+        }
+    }
+
+    /// IPv4-mapped IPv6 address is converted, other IPv6 address is `NotIpv4` error
+    pub fn to_u32(&self) -> Result<u32, IpAddressError> {
+        match self.to_canonical() {
+            IpAddress::Ipv4(ipv4) => Ok(u32::from_be_bytes(ipv4)),
+            IpAddress::Ipv6(_) => Err(IpAddressError::new(IpAddressErrorKind::NotIpv4, &self.to_address())),
         }
     }
 
-    pub fn to_u32(&self) -> u32 {
+    pub fn to_u128(&self) -> u128 {
         match self {
-            IpAddress::Ipv4(ipv4) => {
-                u32::from_be_bytes(*ipv4)
-            }
+            IpAddress::Ipv4(ipv4) => ipv6_to_u128(&ipv4_to_ipv4_mapped(ipv4)),
+            IpAddress::Ipv6(ipv6) => ipv6_to_u128(ipv6),
         }
     }
 
-    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
+    // IPv4 address to IPv4-mapped IPv6 address: 1.2.3.4 -> ::ffff:1.2.3.4
+    pub fn to_ipv6_mapped(&self) -> Self {
         match self {
-            IpAddress::Ipv4(self_ipv4_octets) => IpAddressMask::Ipv4(*self_ipv4_octets, 32).is_matches(socket_addr),
+            IpAddress::Ipv4(ipv4) => IpAddress::Ipv6(ipv4_to_ipv4_mapped(ipv4)),
+            IpAddress::Ipv6(_) => self.clone(),
         }
     }
+
+    // IPv4-mapped IPv6 address to IPv4 address: ::ffff:1.2.3.4 -> 1.2.3.4
+    pub fn to_ipv4_mapped(&self) -> Option<Self> {
+        match self {
+            IpAddress::Ipv4(_) => Some(self.clone()),
+            IpAddress::Ipv6(ipv6) => ipv4_mapped_to_ipv4(ipv6).map(IpAddress::Ipv4),
+        }
+    }
+
+    // IPv4-mapped IPv6 address as IPv4 address, others unchanged
+    pub fn to_canonical(&self) -> Self {
+        self.to_ipv4_mapped().unwrap_or_else(|| self.clone())
+    }
+
+    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
+        IpAddressMask::from_ip_address(self).is_matches(socket_addr)
+    }
 }
 
 impl Display for IpAddress {
@@ -106,23 +235,52 @@ impl Display for IpAddress {
 #[derive(Debug, Clone)]
 pub enum IpAddressMask {
     Ipv4([u8; 4], u8),
+    Ipv6([u8; 16], u8),
 }
 
 impl IpAddressMask {
     pub fn parse_ipv4(addr: &str) -> Option<Self> {
-        let addr_mask_parts = addr.split('/').collect::<Vec<_>>();
-        let (addr_ip, mask) = if addr_mask_parts.len() == 1 {
-            (addr_mask_parts[0], 32)
-        } else if addr_mask_parts.len() == 2 {
-            if let Ok(mask) = addr_mask_parts[1].parse::<u8>() {
-                (addr_mask_parts[0], mask)
-            } else {
-                return None;
-            }
+        Self::parse(addr).filter(|m| matches!(m, IpAddressMask::Ipv4(_, _)))
+    }
+
+    pub fn parse_ipv6(addr: &str) -> Option<Self> {
+        Self::parse(addr).filter(|m| matches!(m, IpAddressMask::Ipv6(_, _)))
+    }
+
+    /// Supports `10.0.0.0/24`, `10.0.0.0/255.255.255.0`, `10.0.*.*`,
+    /// and range which is exactly one CIDR e.g. `10.0.0.0-10.0.0.255`
+    pub fn parse(addr: &str) -> Option<Self> {
+        addr.parse().ok()
+    }
+
+    /// Same as `parse`, and range is expanded to CIDR list e.g. `10.0.0.1-10.0.0.50`
+    pub fn parse_masks(addr: &str) -> Result<Vec<Self>, IpAddressError> {
+        if is_ip_range(addr) {
+            let ip_range = IpRange::parse(addr).ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidRange, addr))?;
+            Ok(ip_range.to_ip_address_masks())
         } else {
-            return None;
-        };
-        parse_ipv4_addr(addr_ip).map(|parts| IpAddressMask::Ipv4(parts, mask))
+            addr.parse().map(|m| vec![m])
+        }
+    }
+
+    pub fn from_ip_address(ip_address: &IpAddress) -> Self {
+        match ip_address {
+            IpAddress::Ipv4(ipv4) => IpAddressMask::Ipv4(*ipv4, 32),
+            IpAddress::Ipv6(ipv6) => IpAddressMask::Ipv6(*ipv6, 128),
+        }
+    }
+
+    pub fn ip_address(&self) -> IpAddress {
+        match self {
+            IpAddressMask::Ipv4(ipv4, _) => IpAddress::Ipv4(*ipv4),
+            IpAddressMask::Ipv6(ipv6, _) => IpAddress::Ipv6(*ipv6),
+        }
+    }
+
+    pub fn mask_len(&self) -> u8 {
+        match self {
+            IpAddressMask::Ipv4(_, mask) | IpAddressMask::Ipv6(_, mask) => *mask,
+        }
     }
 
     pub fn to_address(&self) -> String {
@@ -130,25 +288,33 @@ impl IpAddressMask {
             IpAddressMask::Ipv4(ipv4, mask) => {
                 format!("{}/{}", ipv4.iter().map(|p| p.to_string()).collect::<Vec<_>>().join("."), mask)
             }
+            IpAddressMask::Ipv6(ipv6, mask) => format!("{}/{}", Ipv6Addr::from(*ipv6), mask),
         }
     }
 
-    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
-        match socket_addr {
-            SocketAddr::V4(socket_addr_v4) => {
-                let socket_addr_v4_octets = socket_addr_v4.ip().octets();
-                match self {
-                    IpAddressMask::Ipv4(self_ipv4_octets, mask) => {
-                        let self_ipv4_u32 = ipv4_to_u32(self_ipv4_octets);
-                        let addr_ipv4_u32 = ipv4_to_u32(&socket_addr_v4_octets);
-                        let mask_u32 = ipv4_mask(*mask);
-                        self_ipv4_u32 & mask_u32 == addr_ipv4_u32 & mask_u32
-                    }
-                }
+    // IPv4 mask matches IPv4-mapped IPv6 address, and vice versa
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        match (self, ip_address) {
+            (IpAddressMask::Ipv4(self_ipv4_octets, mask), IpAddress::Ipv4(ipv4_octets)) => {
+                let mask_u32 = ipv4_mask(*mask);
+                ipv4_to_u32(self_ipv4_octets) & mask_u32 == ipv4_to_u32(ipv4_octets) & mask_u32
+            }
+            (IpAddressMask::Ipv6(self_ipv6_octets, mask), IpAddress::Ipv6(ipv6_octets)) => {
+                let mask_u128 = ipv6_mask(*mask);
+                ipv6_to_u128(self_ipv6_octets) & mask_u128 == ipv6_to_u128(ipv6_octets) & mask_u128
+            }
+            (IpAddressMask::Ipv4(_, _), IpAddress::Ipv6(ipv6_octets)) => {
+                ipv4_mapped_to_ipv4(ipv6_octets).map(|ipv4| self.contains_ip_address(&IpAddress::Ipv4(ipv4))).unwrap_or(false)
+            }
+            (IpAddressMask::Ipv6(_, _), IpAddress::Ipv4(ipv4_octets)) => {
+                self.contains_ip_address(&IpAddress::Ipv6(ipv4_to_ipv4_mapped(ipv4_octets)))
             }
-            SocketAddr::V6(_) => false,
         }
     }
+
+    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
+        self.contains_ip_address(&socket_addr.ip().into())
+    }
 }
 
 impl Display for IpAddressMask {
@@ -163,16 +329,29 @@ pub struct IpAddressMaskGroup {
 }
 
 impl IpAddressMaskGroup {
+    // invalid entries are ignored, use `parse_strict` to get errors
     pub fn parse(ip_mask_group: &[String]) -> Self {
         let mut ret = vec![];
         for ip_mask_addr in ip_mask_group {
-            if let Some(ip_mask) = IpAddressMask::parse_ipv4(ip_mask_addr) {
-                ret.push(ip_mask);
+            if let Ok(ip_masks) = IpAddressMask::parse_masks(ip_mask_addr) {
+                ret.extend(ip_masks);
             }
         }
         Self { ip_address_mask_group: ret }
     }
 
+    pub fn parse_strict(ip_mask_group: &[String]) -> Result<Self, IpAddressMaskGroupError> {
+        let mut ret = vec![];
+        let mut errors = vec![];
+        for (index, ip_mask_addr) in ip_mask_group.iter().enumerate() {
+            match IpAddressMask::parse_masks(ip_mask_addr) {
+                Ok(ip_masks) => ret.extend(ip_masks),
+                Err(e) => errors.push((index, e)),
+            }
+        }
+        iff!(errors.is_empty(), Ok(Self { ip_address_mask_group: ret }), Err(IpAddressMaskGroupError { errors }))
+    }
+
     pub fn is_empty(&self) -> bool {
         self.ip_address_mask_group.is_empty()
     }
@@ -184,6 +363,10 @@ impl IpAddressMaskGroup {
     pub fn is_empty_or_matches(&self, socket_addr: &SocketAddr) -> bool {
         self.is_empty() || self.is_matches(socket_addr)
     }
+
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        self.ip_address_mask_group.iter().any(|ip_address_mask| ip_address_mask.contains_ip_address(ip_address))
+    }
 }
 
 impl Display for IpAddressMaskGroup {
@@ -192,6 +375,21 @@ impl Display for IpAddressMaskGroup {
     }
 }
 
+#[derive(Debug, Clone)]
+pub struct IpAddressMaskGroupError {
+    // (index, error) of invalid entries
+    pub errors: Vec<(usize, IpAddressError)>,
+}
+
+impl Display for IpAddressMaskGroupError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{} invalid entries: {}", self.errors.len(),
+               self.errors.iter().map(|(i, e)| format!("#{} {}", i, e)).collect::<Vec<_>>().join(", "))
+    }
+}
+
+impl Error for IpAddressMaskGroupError {}
+
 #[derive(Debug, Clone)]
 pub struct IpAddressAndPort {
     pub ip: IpAddress,
@@ -199,53 +397,189 @@ pub struct IpAddressAndPort {
 }
 
 impl IpAddressAndPort {
+    // `:8080`, `1.2.3.4:8080` or `[::1]:8080`
     pub fn parse(ip_address_and_port: &str) -> Option<Self> {
-        if let Some((ipv4, port)) = parse_ip_and_port(ip_address_and_port) {
-            return Some(IpAddressAndPort {
-                ip: IpAddress::Ipv4(ipv4),
-                port,
-            });
-        }
-        None
+        ip_address_and_port.parse().ok()
     }
 
     pub fn to_address(&self) -> String {
-        format!("{}:{}", self.ip, self.port)
+        format!("{}", self)
     }
 
-    pub fn to_ipv4_and_port(&self) -> ([u8; 4], u16) {
-        match self.ip {
-            IpAddress::Ipv4(ipv4) => (ipv4, self.port),
-        }
+    /// IPv4-mapped IPv6 address is converted, other IPv6 address is `NotIpv4` error
+    pub fn to_ipv4_and_port(&self) -> Result<([u8; 4], u16), IpAddressError> {
+        Ok((self.ip.to_u32()?.to_be_bytes(), self.port))
     }
 }
 
 impl Display for IpAddressAndPort {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "{}:{}", self.ip, self.port)
+        match self.ip {
+            IpAddress::Ipv4(_) => write!(f, "{}:{}", self.ip, self.port),
+            IpAddress::Ipv6(_) => write!(f, "[{}]:{}", self.ip, self.port),
+        }
     }
 }
 
-// :8080 -> 127.0.0.1:8080
-fn parse_ip_and_port(listen: &str) -> Option<([u8; 4], u16)> {
-    let listen_addr = match listen.split(':').next() {
-        None => DEFAULT_LISTEN_ADDR,
-        Some(addr) if addr.is_empty() => DEFAULT_LISTEN_ADDR,
-        Some(addr) => match parse_ipv4_addr(addr) {
-            Some(parsed_ip_address) => parsed_ip_address,
-            None => return None,
-        },
-    };
+impl FromStr for IpAddress {
+    type Err = IpAddressError;
+
+    fn from_str(addr: &str) -> Result<Self, Self::Err> {
+        if addr.contains(':') {
+            parse_ipv6_addr(addr).map(IpAddress::Ipv6).ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidIpv6, addr))
+        } else {
+            parse_ipv4_addr(addr).map(IpAddress::Ipv4).ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidIpv4, addr))
+        }
+    }
+}
+
+impl FromStr for IpAddressMask {
+    type Err = IpAddressError;
+
+    fn from_str(addr: &str) -> Result<Self, Self::Err> {
+        if is_ip_range(addr) {
+            let mut ip_masks = Self::parse_masks(addr)?;
+            return iff!(ip_masks.len() == 1, Ok(ip_masks.remove(0)), Err(IpAddressError::new(IpAddressErrorKind::InvalidRange, addr)));
+        }
+        if addr.contains('*') {
+            return parse_ipv4_wildcard(addr).ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidIpv4, addr));
+        }
+        let max_mask = iff!(addr.contains(':'), 128, 32);
+        let (addr_ip, mask) = split_addr_and_mask(addr, max_mask)?;
+        match addr_ip.parse::<IpAddress>().map_err(|e| IpAddressError::new(e.kind, addr))? {
+            IpAddress::Ipv4(ipv4) => Ok(IpAddressMask::Ipv4(ipv4, mask)),
+            IpAddress::Ipv6(ipv6) => Ok(IpAddressMask::Ipv6(ipv6, mask)),
+        }
+    }
+}
+
+// :8080 -> 127.0.0.1:8080, [::1]:8080 -> ::1 and 8080
+impl FromStr for IpAddressAndPort {
+    type Err = IpAddressError;
+
+    fn from_str(listen: &str) -> Result<Self, Self::Err> {
+        let invalid_format = || IpAddressError::new(IpAddressErrorKind::InvalidFormat, listen);
+        let (listen_addr, listen_port) = if let Some(ipv6_and_port) = listen.strip_prefix('[') {
+            let (ipv6, port) = ipv6_and_port.split_once("]:").ok_or_else(invalid_format)?;
+            let ipv6 = IpAddress::parse_ipv6(ipv6).ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidIpv6, listen))?;
+            (ipv6, port)
+        } else {
+            let (addr, port) = listen.split_once(':').ok_or_else(invalid_format)?;
+            if addr.is_empty() {
+                (IpAddress::Ipv4(DEFAULT_LISTEN_ADDR), port)
+            } else {
+                let ipv4 = IpAddress::parse_ipv4(addr).ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidIpv4, listen))?;
+                (ipv4, port)
+            }
+        };
+        let port = listen_port.parse::<u16>().map_err(|_| IpAddressError::new(IpAddressErrorKind::InvalidPort, listen))?;
+        Ok(IpAddressAndPort { ip: listen_addr, port })
+    }
+}
+
+impl From<Ipv4Addr> for IpAddress {
+    fn from(ipv4: Ipv4Addr) -> Self {
+        IpAddress::Ipv4(ipv4.octets())
+    }
+}
+
+impl From<Ipv6Addr> for IpAddress {
+    fn from(ipv6: Ipv6Addr) -> Self {
+        IpAddress::Ipv6(ipv6.octets())
+    }
+}
+
+impl From<IpAddr> for IpAddress {
+    fn from(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ipv4) => ipv4.into(),
+            IpAddr::V6(ipv6) => ipv6.into(),
+        }
+    }
+}
 
-    let listen_port = match listen.split(':').nth(1) {
-        None => return None,
-        Some(port) => match port.parse::<u16>() {
-            Ok(port) => port,
-            Err(_) => return None,
-        },
+impl From<IpAddress> for IpAddr {
+    fn from(ip_address: IpAddress) -> Self {
+        match ip_address {
+            IpAddress::Ipv4(ipv4) => IpAddr::V4(ipv4.into()),
+            IpAddress::Ipv6(ipv6) => IpAddr::V6(ipv6.into()),
+        }
+    }
+}
+
+// IPv4 address is converted to IPv4-mapped IPv6 address
+impl From<IpAddress> for Ipv6Addr {
+    fn from(ip_address: IpAddress) -> Self {
+        Ipv6Addr::from(ip_address.to_u128())
+    }
+}
+
+impl TryFrom<IpAddress> for Ipv4Addr {
+    type Error = IpAddressError;
+
+    fn try_from(ip_address: IpAddress) -> Result<Self, Self::Error> {
+        match ip_address {
+            IpAddress::Ipv4(ipv4) => Ok(ipv4.into()),
+            IpAddress::Ipv6(_) => Err(IpAddressError::new(IpAddressErrorKind::NotIpv4, &ip_address.to_address())),
+        }
+    }
+}
+
+impl From<SocketAddr> for IpAddressAndPort {
+    fn from(socket_addr: SocketAddr) -> Self {
+        IpAddressAndPort { ip: socket_addr.ip().into(), port: socket_addr.port() }
+    }
+}
+
+impl From<IpAddressAndPort> for SocketAddr {
+    fn from(ip_address_and_port: IpAddressAndPort) -> Self {
+        match ip_address_and_port.ip {
+            IpAddress::Ipv4(ipv4) => SocketAddr::V4(SocketAddrV4::new(ipv4.into(), ip_address_and_port.port)),
+            IpAddress::Ipv6(ipv6) => SocketAddr::V6(SocketAddrV6::new(ipv6.into(), ip_address_and_port.port, 0, 0)),
+        }
+    }
+}
+
+// `addr` -> (addr, max_mask), `addr/mask` -> (addr, mask), `addr/255.255.255.0` -> (addr, 24)
+fn split_addr_and_mask(addr: &str, max_mask: u8) -> Result<(&str, u8), IpAddressError> {
+    let addr_mask_parts = addr.split('/').collect::<Vec<_>>();
+    let (addr_ip, mask) = if addr_mask_parts.len() == 1 {
+        (addr_mask_parts[0], max_mask)
+    } else if addr_mask_parts.len() == 2 {
+        let mask = if max_mask == 32 && addr_mask_parts[1].contains('.') {
+            parse_ipv4_addr(addr_mask_parts[1]).and_then(|netmask| netmask_to_mask_len(ipv4_to_u32(&netmask)))
+        } else {
+            addr_mask_parts[1].parse::<u8>().ok()
+        };
+        let mask = mask.ok_or_else(|| IpAddressError::new(IpAddressErrorKind::InvalidMask, addr))?;
+        (addr_mask_parts[0], mask)
+    } else {
+        return Err(IpAddressError::new(IpAddressErrorKind::InvalidFormat, addr));
     };
+    iff!(mask > max_mask, Err(IpAddressError::new(IpAddressErrorKind::InvalidMask, addr)), Ok((addr_ip, mask)))
+}
 
-    Some((listen_addr, listen_port))
+// 255.255.255.0 -> 24, non-contiguous netmask -> None
+fn netmask_to_mask_len(netmask: u32) -> Option<u8> {
+    let mask_len = netmask.leading_ones() as u8;
+    iff!(ipv4_mask(mask_len) == netmask, Some(mask_len), None)
+}
+
+// `10.0.*.*` -> 10.0.0.0/16, wildcards are only allowed in trailing parts
+fn parse_ipv4_wildcard(addr: &str) -> Option<IpAddressMask> {
+    let addr_parts = addr.split('.').collect::<Vec<_>>();
+    if addr_parts.len() != 4 {
+        return None;
+    }
+    let mask_len = addr_parts.iter().take_while(|p| **p != "*").count();
+    if addr_parts[mask_len..].iter().any(|p| *p != "*") {
+        return None;
+    }
+    let mut ipv4 = [0_u8; 4];
+    for (i, p) in addr_parts[..mask_len].iter().enumerate() {
+        ipv4[i] = p.parse::<u8>().ok()?;
+    }
+    Some(IpAddressMask::Ipv4(ipv4, (mask_len * 8) as u8))
 }
 
 fn ipv4_mask(mask: u8) -> u32 {
@@ -274,10 +608,61 @@ fn get_ipv4_mask(mask_len: u8) -> u32 {
     m
 }
 
-fn ipv4_to_u32(ipv4: &[u8; 4]) -> u32 {
+pub(crate) fn ipv6_mask(mask_len: u8) -> u128 {
+    iff!(mask_len == 0, 0, u128::MAX << (128 - mask_len as u32))
+}
+
+pub(crate) fn ipv4_to_u32(ipv4: &[u8; 4]) -> u32 {
     u32::from_be_bytes(*ipv4)
 }
 
+pub(crate) fn ipv6_to_u128(ipv6: &[u8; 16]) -> u128 {
+    u128::from_be_bytes(*ipv6)
+}
+
+// IPv4 value is in the low 32 bits
+pub(crate) fn value_to_ip_address(is_ipv4: bool, value: u128) -> IpAddress {
+    iff!(is_ipv4, IpAddress::Ipv4((value as u32).to_be_bytes()), IpAddress::Ipv6(value.to_be_bytes()))
+}
+
+pub(crate) fn value_to_ip_address_mask(is_ipv4: bool, value: u128, mask_len: u8) -> IpAddressMask {
+    match value_to_ip_address(is_ipv4, value) {
+        IpAddress::Ipv4(ipv4) => IpAddressMask::Ipv4(ipv4, mask_len),
+        IpAddress::Ipv6(ipv6) => IpAddressMask::Ipv6(ipv6, mask_len),
+    }
+}
+
+// both sides of `-` are IP addresses, e.g. `10.0.0.1-10.0.0.50`, the range itself may be invalid
+fn is_ip_range(addr: &str) -> bool {
+    addr.split_once('-').map(|(start, end)| {
+        start.trim().parse::<IpAddress>().is_ok() && end.trim().parse::<IpAddress>().is_ok()
+    }).unwrap_or(false)
+}
+
+// (IPv4 address, mask length - 96) for IPv4-mapped IPv6 network with mask length at least 96
+fn canonicalize_ip_address_mask(ip_address: &IpAddress, mask_len: u8) -> (IpAddress, u8) {
+    match ip_address.to_ipv4_mapped() {
+        Some(ipv4) if mask_len >= 96 => (ipv4, mask_len - 96),
+        _ => (ip_address.clone(), mask_len),
+    }
+}
+
+fn ipv4_to_ipv4_mapped(ipv4: &[u8; 4]) -> [u8; 16] {
+    let mut ipv6 = [0_u8; 16];
+    ipv6[10] = 0xff;
+    ipv6[11] = 0xff;
+    ipv6[12..].copy_from_slice(ipv4);
+    ipv6
+}
+
+fn ipv4_mapped_to_ipv4(ipv6: &[u8; 16]) -> Option<[u8; 4]> {
+    if ipv6[..10].iter().all(|b| *b == 0) && ipv6[10] == 0xff && ipv6[11] == 0xff {
+        Some([ipv6[12], ipv6[13], ipv6[14], ipv6[15]])
+    } else {
+        None
+    }
+}
+
 fn parse_ipv4_addr(addr: &str) -> Option<[u8; 4]> {
     let addr_parts = addr.split('.').collect::<Vec<_>>();
     if addr_parts.len() != 4 {
@@ -293,18 +678,22 @@ fn parse_ipv4_addr(addr: &str) -> Option<[u8; 4]> {
     parsed_addr().ok()
 }
 
+fn parse_ipv6_addr(addr: &str) -> Option<[u8; 16]> {
+    addr.parse::<Ipv6Addr>().ok().map(|ipv6| ipv6.octets())
+}
+
 #[test]
 fn test_ip_address_is_matches() {
     let addr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1)), 123);
     let addr2 = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 2)), 123);
-    assert_eq!(true, IpAddressMask::parse_ipv4("127.0.0.1").unwrap().is_matches(&addr));
-    assert_eq!(true, IpAddressMask::parse_ipv4("127.0.0.1/32").unwrap().is_matches(&addr));
-    assert_eq!(true, IpAddressMask::parse_ipv4("127.0.0.1/31").unwrap().is_matches(&addr));
-    assert_eq!(true, IpAddressMask::parse_ipv4("127.0.0.1/30").unwrap().is_matches(&addr));
-    assert_eq!(false, IpAddressMask::parse_ipv4("127.0.0.1").unwrap().is_matches(&addr2));
-    assert_eq!(false, IpAddressMask::parse_ipv4("127.0.0.1/32").unwrap().is_matches(&addr2));
-    assert_eq!(false, IpAddressMask::parse_ipv4("127.0.0.1/31").unwrap().is_matches(&addr2));
-    assert_eq!(true, IpAddressMask::parse_ipv4("127.0.0.1/30").unwrap().is_matches(&addr2));
+    assert!(IpAddressMask::parse_ipv4("127.0.0.1").unwrap().is_matches(&addr));
+    assert!(IpAddressMask::parse_ipv4("127.0.0.1/32").unwrap().is_matches(&addr));
+    assert!(IpAddressMask::parse_ipv4("127.0.0.1/31").unwrap().is_matches(&addr));
+    assert!(IpAddressMask::parse_ipv4("127.0.0.1/30").unwrap().is_matches(&addr));
+    assert!(!IpAddressMask::parse_ipv4("127.0.0.1").unwrap().is_matches(&addr2));
+    assert!(!IpAddressMask::parse_ipv4("127.0.0.1/32").unwrap().is_matches(&addr2));
+    assert!(!IpAddressMask::parse_ipv4("127.0.0.1/31").unwrap().is_matches(&addr2));
+    assert!(IpAddressMask::parse_ipv4("127.0.0.1/30").unwrap().is_matches(&addr2));
 }
 
 #[test]
@@ -319,15 +708,15 @@ fn test_ip_address_port() {
 
 #[test]
 fn test_ip_address_mask_group_is_matches() {
-    let group = IpAddressMaskGroup::parse(&vec!["127.0.0.1".to_owned(), "10.0.0.0/24".to_owned()]);
+    let group = IpAddressMaskGroup::parse(&["127.0.0.1".to_owned(), "10.0.0.0/24".to_owned()]);
     let addr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 1)), 123);
-    assert_eq!(true, group.is_matches(&addr));
+    assert!(group.is_matches(&addr));
     let addr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(127, 0, 0, 2)), 123);
-    assert_eq!(false, group.is_matches(&addr));
+    assert!(!group.is_matches(&addr));
     let addr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 2)), 123);
-    assert_eq!(true, group.is_matches(&addr));
+    assert!(group.is_matches(&addr));
     let addr = SocketAddr::new(std::net::IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 1, 2)), 123);
-    assert_eq!(false, group.is_matches(&addr));
+    assert!(!group.is_matches(&addr));
 }
 
 #[test]
@@ -391,3 +780,176 @@ fn test_ip_and_ip_mask_matcher() {
         assert!(matcher.contains_ip_address_mask(&IpAddress::Ipv4([192, 168, 1, 2]), 31));
     }
 }
+
+#[test]
+fn test_ipv6_address_parse() {
+    assert_eq!("::1", IpAddress::parse("::1").unwrap().to_string());
+    assert_eq!("::", IpAddress::parse("0:0:0:0:0:0:0:0").unwrap().to_string());
+    assert_eq!("2001:db8::1", IpAddress::parse("2001:0DB8:0000:0000:0000:0000:0000:0001").unwrap().to_string());
+    assert_eq!("2001:db8:0:1:1:1:1:1", IpAddress::parse("2001:db8:0:1:1:1:1:1").unwrap().to_string());
+    assert_eq!("2001:0:0:1::1", IpAddress::parse("2001:0:0:1:0:0:0:1").unwrap().to_string());
+    assert_eq!("::ffff:1.2.3.4", IpAddress::parse("::ffff:1.2.3.4").unwrap().to_string());
+    assert_eq!("::ffff:1.2.3.4", IpAddress::parse("::ffff:0102:0304").unwrap().to_string());
+    assert_eq!(Some(IpAddress::Ipv4([1, 2, 3, 4])), IpAddress::parse("::ffff:1.2.3.4").unwrap().to_ipv4_mapped());
+    assert_eq!(None, IpAddress::parse("::1").unwrap().to_ipv4_mapped());
+    assert_eq!(0x0102_0304, IpAddress::parse("::ffff:1.2.3.4").unwrap().to_u32().unwrap());
+    assert_eq!(IpAddressErrorKind::NotIpv4, IpAddress::parse("::1").unwrap().to_u32().unwrap_err().kind);
+    assert_eq!(([1, 2, 3, 4], 80), IpAddressAndPort::parse("[::ffff:1.2.3.4]:80").unwrap().to_ipv4_and_port().unwrap());
+    assert_eq!(IpAddressErrorKind::NotIpv4, IpAddressAndPort::parse("[::1]:80").unwrap().to_ipv4_and_port().unwrap_err().kind);
+    assert!(IpAddress::parse("1::2::3").is_none());
+    assert!(IpAddress::parse("1:2:3:4:5:6:7:8:9").is_none());
+    assert!(IpAddress::parse("12345::").is_none());
+    assert!(IpAddress::parse("::g").is_none());
+    assert!(IpAddress::parse("1.2.3").is_none());
+}
+
+#[test]
+fn test_ipv6_address_mask_is_matches() {
+    let addr = SocketAddr::new(IpAddr::V6("2001:db8::1".parse().unwrap()), 123);
+    let addr2 = SocketAddr::new(IpAddr::V6("2001:db9::1".parse().unwrap()), 123);
+    assert!(IpAddressMask::parse("2001:db8::1").unwrap().is_matches(&addr));
+    assert!(IpAddressMask::parse("2001:db8::/32").unwrap().is_matches(&addr));
+    assert!(!IpAddressMask::parse("2001:db8::/32").unwrap().is_matches(&addr2));
+    assert!(IpAddressMask::parse("2001:db8::/31").unwrap().is_matches(&addr2));
+    assert!(IpAddressMask::parse("::/0").unwrap().is_matches(&addr2));
+    assert!(IpAddressMask::parse("::/129").is_none());
+    assert!(IpAddressMask::parse("1.2.3.4/33").is_none());
+    assert_eq!("2001:db8::/32", IpAddressMask::parse("2001:db8::/32").unwrap().to_string());
+
+    // IPv4 mask matches IPv4-mapped IPv6 address and vice versa
+    let mapped_addr = SocketAddr::new(IpAddr::V6("::ffff:10.0.0.1".parse().unwrap()), 123);
+    assert!(IpAddressMask::parse("10.0.0.0/8").unwrap().is_matches(&mapped_addr));
+    assert!(!IpAddressMask::parse("11.0.0.0/8").unwrap().is_matches(&mapped_addr));
+    let ipv4_addr = SocketAddr::new(IpAddr::V4(std::net::Ipv4Addr::new(10, 0, 0, 1)), 123);
+    assert!(IpAddressMask::parse("::ffff:0:0/96").unwrap().is_matches(&ipv4_addr));
+    assert!(!IpAddressMask::parse("2001:db8::/32").unwrap().is_matches(&ipv4_addr));
+
+    let group = IpAddressMaskGroup::parse(&["127.0.0.1".to_owned(), "fd00::/8".to_owned(), "bad".to_owned()]);
+    assert_eq!("[127.0.0.1/32, fd00::/8]", group.to_string());
+    assert!(group.is_matches(&SocketAddr::new(IpAddr::V6("fd12::1".parse().unwrap()), 123)));
+    assert!(!group.is_matches(&addr));
+}
+
+#[test]
+fn test_ipv6_address_port() {
+    assert_eq!("[::1]:8080", IpAddressAndPort::parse("[::1]:8080").unwrap().to_string());
+    assert_eq!("[2001:db8::1]:80", IpAddressAndPort::parse("[2001:DB8::1]:80").unwrap().to_address());
+    assert!(IpAddressAndPort::parse("[::1]").is_none());
+    assert!(IpAddressAndPort::parse("::1:8080").is_none());
+    assert!(IpAddressAndPort::parse("[::1]:65536").is_none());
+    assert!(IpAddressAndPort::parse("1.1.1.1").is_none());
+}
+
+#[test]
+fn test_ipv6_ip_and_ip_mask_matcher() {
+    let mut matcher = IpAndIpMaskMatcher::new();
+    assert!(matcher.add_ip_address_mask(&IpAddress::parse("2001:db8::").unwrap(), 32));
+    assert!(!matcher.add_ip_address_mask(&IpAddress::parse("2001:db8::").unwrap(), 129));
+    matcher.add_ip_address(&IpAddress::parse("fe80::1").unwrap());
+    matcher.add_ip_address_mask(&IpAddress::Ipv4([10, 0, 0, 0]), 8);
+    assert!(matcher.contains_ip_address(&IpAddress::parse("2001:db8:1::1").unwrap()));
+    assert!(!matcher.contains_ip_address(&IpAddress::parse("2001:db9::1").unwrap()));
+    assert!(matcher.contains_ip_address(&IpAddress::parse("fe80::1").unwrap()));
+    assert!(!matcher.contains_ip_address(&IpAddress::parse("fe80::2").unwrap()));
+    assert!(matcher.contains_ip_address_mask(&IpAddress::parse("2001:db8::").unwrap(), 48));
+    assert!(!matcher.contains_ip_address_mask(&IpAddress::parse("2001:db8::").unwrap(), 31));
+    assert!(matcher.contains_ip_address(&IpAddress::parse("::ffff:10.1.2.3").unwrap()));
+    assert!(!matcher.contains_ip_address(&IpAddress::parse("::ffff:11.1.2.3").unwrap()));
+}
+
+#[test]
+fn test_ipv4_mapped_ip_and_ip_mask_matcher() {
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    let mut matcher = IpAndIpMaskMatcher::new();
+    assert!(matcher.add_ip_address_mask(&ip("::ffff:10.0.0.0"), 104));
+    matcher.add_ip_address(&ip("::ffff:192.168.1.1"));
+    assert!(matcher.contains_ip_address(&ip("10.0.0.1")));
+    assert!(matcher.contains_ip_address(&ip("::ffff:10.0.0.1")));
+    assert!(matcher.contains_ip_address(&ip("192.168.1.1")));
+    assert!(!matcher.contains_ip_address(&ip("192.168.1.2")));
+    assert!(matcher.contains_ip_address_mask(&ip("10.1.0.0"), 16));
+    assert!(matcher.contains_ip_address_mask(&ip("::ffff:10.1.0.0"), 112));
+    assert!(!matcher.contains_ip_address_mask(&ip("10.0.0.0"), 7));
+    assert!(!matcher.contains_ip_address_mask(&ip("::ffff:10.0.0.0"), 103));
+
+    let mut matcher = IpAndIpMaskMatcher::new();
+    matcher.add_ip_address_mask(&ip("10.0.0.0"), 8);
+    assert!(matcher.contains_ip_address_mask(&ip("::ffff:10.2.0.0"), 112));
+    assert!(!matcher.contains_ip_address_mask(&ip("::ffff:0.0.0.0"), 96));
+
+    // IPv6 network covering the whole IPv4-mapped range
+    let mut matcher = IpAndIpMaskMatcher::new();
+    matcher.add_ip_address_mask(&ip("::"), 64);
+    assert!(matcher.contains_ip_address(&ip("10.0.0.1")));
+    assert!(matcher.contains_ip_address_mask(&ip("10.0.0.0"), 8));
+    assert!(!matcher.contains_ip_address(&ip("2001:db8::1")));
+}
+
+#[test]
+fn test_from_str() {
+    assert_eq!(IpAddress::Ipv4([1, 2, 3, 4]), "1.2.3.4".parse::<IpAddress>().unwrap());
+    assert_eq!(IpAddressErrorKind::InvalidIpv4, "1.2.3.256".parse::<IpAddress>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidIpv6, "1::2::3".parse::<IpAddress>().unwrap_err().kind);
+    assert_eq!("Invalid IPv4 address: 1.2.3", "1.2.3".parse::<IpAddress>().unwrap_err().to_string());
+    assert_eq!("10.0.0.0/8", "10.0.0.0/8".parse::<IpAddressMask>().unwrap().to_string());
+    assert_eq!("fd00::/8", "fd00::/8".parse::<IpAddressMask>().unwrap().to_string());
+    assert_eq!(IpAddressErrorKind::InvalidMask, "10.0.0.0/33".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidMask, "10.0.0.0/x".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidFormat, "10.0.0.0/8/8".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidIpv4, "10.0.0/8".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!("[::1]:80", "[::1]:80".parse::<IpAddressAndPort>().unwrap().to_string());
+    assert_eq!(IpAddressErrorKind::InvalidPort, "1.1.1.1:x".parse::<IpAddressAndPort>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidFormat, "1.1.1.1".parse::<IpAddressAndPort>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidIpv6, "[::x]:80".parse::<IpAddressAndPort>().unwrap_err().kind);
+}
+
+#[test]
+fn test_std_net_conversions() {
+    let ip_address: IpAddress = Ipv4Addr::new(1, 2, 3, 4).into();
+    assert_eq!(IpAddress::Ipv4([1, 2, 3, 4]), ip_address);
+    assert_eq!(Ipv4Addr::new(1, 2, 3, 4), Ipv4Addr::try_from(ip_address.clone()).unwrap());
+    assert_eq!("::ffff:1.2.3.4".parse::<Ipv6Addr>().unwrap(), Ipv6Addr::from(ip_address.clone()));
+    assert_eq!(IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4)), IpAddr::from(ip_address));
+    let ip_address: IpAddress = IpAddr::V6(Ipv6Addr::LOCALHOST).into();
+    assert_eq!("::1", ip_address.to_string());
+    assert_eq!(IpAddressErrorKind::NotIpv4, Ipv4Addr::try_from(ip_address).unwrap_err().kind);
+
+    let socket_addr: SocketAddr = "[2001:db8::1]:443".parse().unwrap();
+    let ip_address_and_port = IpAddressAndPort::from(socket_addr);
+    assert_eq!("[2001:db8::1]:443", ip_address_and_port.to_string());
+    assert_eq!(socket_addr, SocketAddr::from(ip_address_and_port));
+    let socket_addr: SocketAddr = "127.0.0.1:80".parse().unwrap();
+    assert_eq!(socket_addr, SocketAddr::from(IpAddressAndPort::parse(":80").unwrap()));
+}
+
+#[test]
+fn test_ip_address_mask_parse_extended() {
+    assert_eq!("10.0.0.0/24", IpAddressMask::parse("10.0.0.0/255.255.255.0").unwrap().to_string());
+    assert_eq!("10.0.0.0/0", IpAddressMask::parse("10.0.0.0/0.0.0.0").unwrap().to_string());
+    assert_eq!(IpAddressErrorKind::InvalidMask, "10.0.0.0/255.0.255.0".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!("10.0.0.0/16", IpAddressMask::parse("10.0.*.*").unwrap().to_string());
+    assert_eq!("10.1.2.0/24", IpAddressMask::parse_ipv4("10.1.2.*").unwrap().to_string());
+    assert_eq!("0.0.0.0/0", IpAddressMask::parse("*.*.*.*").unwrap().to_string());
+    assert!(IpAddressMask::parse("10.*.0.*").is_none());
+    assert!(IpAddressMask::parse("10.0.*.*/16").is_none());
+    assert!(IpAddressMask::parse("10.0.*").is_none());
+    assert_eq!("10.0.0.0/24", IpAddressMask::parse("10.0.0.0-10.0.0.255").unwrap().to_string());
+    assert_eq!(IpAddressErrorKind::InvalidRange, "10.0.0.1-10.0.0.50".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!(8, IpAddressMask::parse_masks("10.0.0.1-10.0.0.50").unwrap().len());
+    assert_eq!(IpAddressErrorKind::InvalidRange, IpAddressMask::parse_masks("10.0.0.9-10.0.0.1").unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidRange, IpAddressMask::parse_masks("10.0.0.1-::1").unwrap_err().kind);
+    assert_eq!("Invalid IPv4 address: bad-entry", IpAddressMask::parse_masks("bad-entry").unwrap_err().to_string());
+    assert_eq!(IpAddressErrorKind::InvalidIpv4, "10.0.0.1-x".parse::<IpAddressMask>().unwrap_err().kind);
+    assert_eq!(IpAddressErrorKind::InvalidIpv6, "fe80::1-x".parse::<IpAddressMask>().unwrap_err().kind);
+}
+
+#[test]
+fn test_ip_address_mask_group_parse_strict() {
+    let entries = ["10.0.0.1-10.0.0.2", "bad", "192.168.*.*", "1.2.3.4/33", "::1"].iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let group = IpAddressMaskGroup::parse(&entries);
+    assert_eq!("[10.0.0.1/32, 10.0.0.2/32, 192.168.0.0/16, ::1/128]", group.to_string());
+    let error = IpAddressMaskGroup::parse_strict(&entries).unwrap_err();
+    assert_eq!(vec![1, 3], error.errors.iter().map(|(i, _)| *i).collect::<Vec<_>>());
+    assert_eq!("2 invalid entries: #1 Invalid IPv4 address: bad, #3 Invalid mask: 1.2.3.4/33", error.to_string());
+    assert_eq!(4, IpAddressMaskGroup::parse_strict(&[entries[0].clone(), entries[2].clone(), entries[4].clone()]).unwrap().ip_address_mask_group.len());
+}
@@ -0,0 +1,220 @@
+use std::fmt::{self, Display, Formatter};
+use std::net::SocketAddr;
+
+use crate::util_net::{IpAddressMask, IpAddressMaskGroup};
+use crate::util_net_port::PortRange;
+use crate::{iff, opt_result, opt_value_result, simple_error, XResult};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclAction {
+    Allow,
+    Deny,
+}
+
+impl AclAction {
+    pub fn parse(action: &str) -> Option<Self> {
+        match action {
+            "allow" => Some(AclAction::Allow),
+            "deny" => Some(AclAction::Deny),
+            _ => None,
+        }
+    }
+}
+
+impl Display for AclAction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            AclAction::Allow => write!(f, "allow"),
+            AclAction::Deny => write!(f, "deny"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AclRule {
+    pub action: AclAction,
+    // `None` matches all addresses
+    pub ip_address_mask_group: Option<IpAddressMaskGroup>,
+    // `None` matches all ports
+    pub ports: Option<PortRange>,
+}
+
+impl AclRule {
+    pub fn new(action: AclAction, ip_address_mask_group: Option<IpAddressMaskGroup>, ports: Option<PortRange>) -> Self {
+        Self { action, ip_address_mask_group, ports }
+    }
+
+    /// Parse rule like `allow 10.0.0.0/8 192.168.0.0/16 port 80,443,8000-8100` or `deny all`
+    pub fn parse(rule: &str) -> XResult<Self> {
+        let mut tokens = rule.split_whitespace();
+        let action = match tokens.next() {
+            None => return simple_error!("Empty ACL rule"),
+            Some(action) => opt_value_result!(AclAction::parse(action), "Unknown ACL action: {}, rule: {}", action, rule),
+        };
+        let mut ip_address_masks = vec![];
+        let mut is_all = false;
+        let mut ports = None;
+        while let Some(token) = tokens.next() {
+            if token == "all" {
+                is_all = true;
+            } else if token == "port" {
+                let ports_token = opt_value_result!(tokens.next(), "Missing ports, rule: {}", rule);
+                ports = Some(opt_result!(PortRange::parse(ports_token), "Invalid ACL rule: {}, error: {}", rule));
+            } else {
+                ip_address_masks.extend(opt_result!(IpAddressMask::parse_masks(token), "Invalid ACL rule: {}, error: {}", rule));
+            }
+        }
+        if is_all != ip_address_masks.is_empty() {
+            return simple_error!("ACL rule must have either `all` or addresses, rule: {}", rule);
+        }
+        let ip_address_mask_group = iff!(is_all, None, Some(IpAddressMaskGroup { ip_address_mask_group: ip_address_masks }));
+        Ok(Self::new(action, ip_address_mask_group, ports))
+    }
+
+    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
+        let is_address_matches = self.ip_address_mask_group.as_ref()
+            .map(|g| g.is_matches(socket_addr)).unwrap_or(true);
+        let is_port_matches = self.ports.as_ref()
+            .map(|ports| ports.contains(socket_addr.port()))
+            .unwrap_or(true);
+        is_address_matches && is_port_matches
+    }
+}
+
+impl Display for AclRule {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.action)?;
+        match &self.ip_address_mask_group {
+            None => write!(f, " all")?,
+            Some(group) => for ip_address_mask in &group.ip_address_mask_group {
+                write!(f, " {}", ip_address_mask)?;
+            },
+        }
+        if let Some(ports) = &self.ports {
+            write!(f, " port {}", ports)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AclExplain<'a> {
+    pub action: AclAction,
+    // index of the matched rule, `None` means decided by default action
+    pub rule_index: Option<usize>,
+    pub rule: Option<&'a AclRule>,
+}
+
+impl Display for AclExplain<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match (&self.rule_index, &self.rule) {
+            (Some(index), Some(rule)) => write!(f, "{} by rule #{}: {}", self.action, index, rule),
+            _ => write!(f, "{} by default action", self.action),
+        }
+    }
+}
+
+/// Ordered allow/deny rules, the first matched rule decides
+#[derive(Debug, Clone)]
+pub struct Acl {
+    pub rules: Vec<AclRule>,
+    pub default_action: AclAction,
+}
+
+impl Acl {
+    pub fn new(default_action: AclAction) -> Self {
+        Self { rules: vec![], default_action }
+    }
+
+    /// Parse rules separated by `;` or new line, `#` starts a comment,
+    /// `default allow` or `default deny` sets default action (default is deny), e.g.
+    /// ```text
+    /// allow 10.0.0.0/8;
+    /// deny 10.1.0.0/16 port 22;
+    /// allow all;
+    /// ```
+    pub fn parse(config: &str) -> XResult<Self> {
+        let mut acl = Self::new(AclAction::Deny);
+        for (line_index, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            for rule in line.split(';').map(str::trim).filter(|r| !r.is_empty()) {
+                if let Some(default_action) = rule.strip_prefix("default ") {
+                    acl.default_action = opt_value_result!(AclAction::parse(default_action.trim()),
+                        "Unknown default action at line {}: {}", line_index + 1, rule);
+                } else {
+                    acl.add_rule(opt_result!(AclRule::parse(rule), "Parse ACL at line {} failed: {}", line_index + 1));
+                }
+            }
+        }
+        Ok(acl)
+    }
+
+    pub fn add_rule(&mut self, rule: AclRule) -> &mut Self {
+        self.rules.push(rule);
+        self
+    }
+
+    pub fn is_allowed(&self, socket_addr: &SocketAddr) -> bool {
+        let action = self.rules.iter().find(|rule| rule.is_matches(socket_addr))
+            .map(|rule| rule.action).unwrap_or(self.default_action);
+        action == AclAction::Allow
+    }
+
+    /// Which rule decided the address
+    pub fn explain(&self, socket_addr: &SocketAddr) -> AclExplain<'_> {
+        match self.rules.iter().enumerate().find(|(_, rule)| rule.is_matches(socket_addr)) {
+            Some((index, rule)) => AclExplain { action: rule.action, rule_index: Some(index), rule: Some(rule) },
+            None => AclExplain { action: self.default_action, rule_index: None, rule: None },
+        }
+    }
+}
+
+impl Display for Acl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        for rule in &self.rules {
+            writeln!(f, "{};", rule)?;
+        }
+        write!(f, "default {};", self.default_action)
+    }
+}
+
+#[test]
+fn test_acl() {
+    let acl = Acl::parse(r##"
+# internal networks
+allow 10.0.0.0/8; deny 10.1.0.0/16;
+deny 192.168.0.0/16 port 22,8000-8100
+allow 192.168.0.0/16
+default deny
+"##).unwrap();
+    let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+    assert!(acl.is_allowed(&addr("10.0.0.1:80")));
+    assert!(acl.is_allowed(&addr("10.1.0.1:80")));
+    assert!(acl.is_allowed(&addr("[::ffff:10.1.0.1]:80")));
+    assert!(!acl.is_allowed(&addr("192.168.1.1:22")));
+    assert!(!acl.is_allowed(&addr("192.168.1.1:8050")));
+    assert!(acl.is_allowed(&addr("192.168.1.1:8101")));
+    assert!(!acl.is_allowed(&addr("8.8.8.8:80")));
+    assert_eq!("allow by rule #0: allow 10.0.0.0/8", acl.explain(&addr("10.1.0.1:80")).to_string());
+    assert_eq!("deny by rule #2: deny 192.168.0.0/16 port 22,8000-8100", acl.explain(&addr("192.168.1.1:22")).to_string());
+    assert_eq!("deny by default action", acl.explain(&addr("8.8.8.8:80")).to_string());
+
+    let acl = Acl::parse("deny 10.1.0.0/16; allow 10.0.0.0/8; deny all;").unwrap();
+    assert!(!acl.is_allowed(&addr("10.1.0.1:80")));
+    assert!(acl.is_allowed(&addr("10.2.0.1:80")));
+    assert_eq!(Some(2), acl.explain(&addr("1.1.1.1:80")).rule_index);
+    assert_eq!("deny 10.1.0.0/16;\nallow 10.0.0.0/8;\ndeny all;\ndefault deny;", acl.to_string());
+}
+
+#[test]
+fn test_acl_parse_error() {
+    assert!(Acl::parse("permit all").is_err());
+    assert!(Acl::parse("allow").is_err());
+    assert!(Acl::parse("allow all 10.0.0.0/8").is_err());
+    assert!(Acl::parse("allow 10.0.0.0/33").is_err());
+    assert!(Acl::parse("allow all port 80-70").is_err());
+    assert!(Acl::parse("allow all port").is_err());
+    assert!(Acl::parse("default reject").is_err());
+    let e = Acl::parse("allow all;\ndeny 1.2.3").unwrap_err();
+    assert!(e.to_string().contains("line 2"));
+}
@@ -0,0 +1,412 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::iff;
+use crate::util_net::{ipv4_to_u32, ipv6_mask, ipv6_to_u128, value_to_ip_address, value_to_ip_address_mask, IpAddress, IpAddressMask};
+
+const IPV4_MAX: u128 = u32::MAX as u128;
+const IPV6_MAX: u128 = u128::MAX;
+// IPv4-mapped IPv6 addresses `::ffff:0:0/96`
+const IPV4_MAPPED_START: u128 = 0xffff << 32;
+const IPV4_MAPPED_END: u128 = IPV4_MAPPED_START | IPV4_MAX;
+
+/// Inclusive address range, start and end are in the same family
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IpRange {
+    pub start: IpAddress,
+    pub end: IpAddress,
+}
+
+impl IpRange {
+    pub fn new(start: IpAddress, end: IpAddress) -> Option<Self> {
+        if start.is_ipv4() != end.is_ipv4() || start > end {
+            return None;
+        }
+        Some(Self { start, end })
+    }
+
+    // `10.0.0.1-10.0.0.50`
+    pub fn parse(range: &str) -> Option<Self> {
+        let (start, end) = range.split_once('-')?;
+        Self::new(IpAddress::parse(start.trim())?, IpAddress::parse(end.trim())?)
+    }
+
+    pub fn from_ip_address_mask(ip_address_mask: &IpAddressMask) -> Self {
+        let (is_ipv4, start, end) = mask_to_interval(ip_address_mask);
+        Self { start: value_to_ip_address(is_ipv4, start), end: value_to_ip_address(is_ipv4, end) }
+    }
+
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        ip_address.is_ipv4() == self.start.is_ipv4() && &self.start <= ip_address && ip_address <= &self.end
+    }
+
+    /// Minimal CIDR list which covers exactly this range
+    pub fn to_ip_address_masks(&self) -> Vec<IpAddressMask> {
+        let is_ipv4 = self.start.is_ipv4();
+        let mut masks = vec![];
+        interval_to_masks(is_ipv4, to_value(&self.start), to_value(&self.end), &mut masks);
+        masks
+    }
+}
+
+impl Display for IpRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+/// Set of addresses, kept as sorted non-overlapping intervals per family,
+/// IPv4-mapped IPv6 ranges (inside `::ffff:0:0/96`) and addresses are added and looked up as IPv4
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpRangeSet {
+    ipv4_intervals: Vec<(u128, u128)>,
+    ipv6_intervals: Vec<(u128, u128)>,
+}
+
+impl IpRangeSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_ip_address_masks(ip_address_masks: &[IpAddressMask]) -> Self {
+        let mut set = Self::new();
+        for ip_address_mask in ip_address_masks {
+            set.push_interval(mask_to_interval(ip_address_mask));
+        }
+        set.normalize();
+        set
+    }
+
+    pub fn from_ip_ranges(ip_ranges: &[IpRange]) -> Self {
+        let mut set = Self::new();
+        for ip_range in ip_ranges {
+            set.push_interval(range_to_interval(ip_range));
+        }
+        set.normalize();
+        set
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ipv4_intervals.is_empty() && self.ipv6_intervals.is_empty()
+    }
+
+    pub fn add_ip_address_mask(&mut self, ip_address_mask: &IpAddressMask) {
+        self.insert_interval(mask_to_interval(ip_address_mask));
+    }
+
+    pub fn add_ip_range(&mut self, ip_range: &IpRange) {
+        self.insert_interval(range_to_interval(ip_range));
+    }
+
+    /// IPv4 address is also looked up in IPv6 intervals which cover `::ffff:0:0/96` partially, e.g. `::/0`
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        let ip_address = ip_address.to_canonical();
+        let value = to_value(&ip_address);
+        if ip_address.is_ipv4() {
+            intervals_contain(&self.ipv4_intervals, value) || intervals_contain(&self.ipv6_intervals, IPV4_MAPPED_START | value)
+        } else {
+            intervals_contain(&self.ipv6_intervals, value)
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        let mut set = self.clone();
+        set.ipv4_intervals.extend_from_slice(&other.ipv4_intervals);
+        set.ipv6_intervals.extend_from_slice(&other.ipv6_intervals);
+        set.normalize();
+        set
+    }
+
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            ipv4_intervals: intersect_intervals(&self.ipv4_intervals, &other.ipv4_intervals),
+            ipv6_intervals: intersect_intervals(&self.ipv6_intervals, &other.ipv6_intervals),
+        }
+    }
+
+    pub fn difference(&self, other: &Self) -> Self {
+        self.intersection(&other.complement())
+    }
+
+    /// Complement in both the whole IPv4 and the whole IPv6 address space
+    pub fn complement(&self) -> Self {
+        Self {
+            ipv4_intervals: complement_intervals(&self.ipv4_intervals, IPV4_MAX),
+            ipv6_intervals: complement_intervals(&self.ipv6_intervals, IPV6_MAX),
+        }
+    }
+
+    pub fn to_ip_ranges(&self) -> Vec<IpRange> {
+        self.iter_intervals().map(|(is_ipv4, start, end)| IpRange {
+            start: value_to_ip_address(is_ipv4, start),
+            end: value_to_ip_address(is_ipv4, end),
+        }).collect()
+    }
+
+    /// Minimal CIDR list, IPv4 first
+    pub fn to_ip_address_masks(&self) -> Vec<IpAddressMask> {
+        let mut masks = vec![];
+        for (is_ipv4, start, end) in self.iter_intervals() {
+            interval_to_masks(is_ipv4, start, end, &mut masks);
+        }
+        masks
+    }
+
+    pub(crate) fn get_ipv4_intervals(&self) -> &[(u128, u128)] {
+        &self.ipv4_intervals
+    }
+
+    pub(crate) fn get_ipv6_intervals(&self) -> &[(u128, u128)] {
+        &self.ipv6_intervals
+    }
+
+    fn iter_intervals(&self) -> impl Iterator<Item=(bool, u128, u128)> + '_ {
+        self.ipv4_intervals.iter().map(|(s, e)| (true, *s, *e))
+            .chain(self.ipv6_intervals.iter().map(|(s, e)| (false, *s, *e)))
+    }
+
+    // push without merging, `normalize` is required after pushing
+    fn push_interval(&mut self, interval: (bool, u128, u128)) {
+        let (is_ipv4, start, end) = canonicalize_interval(interval);
+        if is_ipv4 {
+            self.ipv4_intervals.push((start, end));
+        } else {
+            self.ipv6_intervals.push((start, end));
+        }
+    }
+
+    // insert and merge with neighbours, keeps intervals sorted and non-overlapping
+    fn insert_interval(&mut self, interval: (bool, u128, u128)) {
+        let (is_ipv4, start, end) = canonicalize_interval(interval);
+        insert_interval(iff!(is_ipv4, &mut self.ipv4_intervals, &mut self.ipv6_intervals), start, end);
+    }
+
+    fn normalize(&mut self) {
+        normalize_intervals(&mut self.ipv4_intervals);
+        normalize_intervals(&mut self.ipv6_intervals);
+    }
+}
+
+/// Merge adjacent or overlapping CIDRs into minimal CIDR list
+pub fn aggregate(ip_address_masks: &[IpAddressMask]) -> Vec<IpAddressMask> {
+    IpRangeSet::from_ip_address_masks(ip_address_masks).to_ip_address_masks()
+}
+
+/// e.g. `10.0.0.0/8` minus `10.1.0.0/16`
+pub fn subtract(ip_address_masks: &[IpAddressMask], excluded_ip_address_masks: &[IpAddressMask]) -> Vec<IpAddressMask> {
+    IpRangeSet::from_ip_address_masks(ip_address_masks)
+        .difference(&IpRangeSet::from_ip_address_masks(excluded_ip_address_masks))
+        .to_ip_address_masks()
+}
+
+pub fn intersect(ip_address_masks: &[IpAddressMask], other_ip_address_masks: &[IpAddressMask]) -> Vec<IpAddressMask> {
+    IpRangeSet::from_ip_address_masks(ip_address_masks)
+        .intersection(&IpRangeSet::from_ip_address_masks(other_ip_address_masks))
+        .to_ip_address_masks()
+}
+
+fn normalize_intervals(intervals: &mut Vec<(u128, u128)>) {
+    intervals.sort_unstable();
+    let mut merged: Vec<(u128, u128)> = Vec::with_capacity(intervals.len());
+    for (start, end) in intervals.drain(..) {
+        match merged.last_mut() {
+            Some(last) if last.1 == u128::MAX || start <= last.1 + 1 => {
+                if end > last.1 {
+                    last.1 = end;
+                }
+            }
+            _ => merged.push((start, end)),
+        }
+    }
+    *intervals = merged;
+}
+
+fn insert_interval(intervals: &mut Vec<(u128, u128)>, start: u128, end: u128) {
+    // intervals overlapping or adjacent to `start..=end` are in `from..to`
+    let from = intervals.partition_point(|(_, e)| e.saturating_add(1) < start);
+    let to = intervals.partition_point(|(s, _)| *s <= end.saturating_add(1));
+    if from < to {
+        let merged = (start.min(intervals[from].0), end.max(intervals[to - 1].1));
+        intervals.splice(from..to, [merged]);
+    } else {
+        intervals.insert(from, (start, end));
+    }
+}
+
+fn intersect_intervals(intervals1: &[(u128, u128)], intervals2: &[(u128, u128)]) -> Vec<(u128, u128)> {
+    let mut result = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < intervals1.len() && j < intervals2.len() {
+        let start = intervals1[i].0.max(intervals2[j].0);
+        let end = intervals1[i].1.min(intervals2[j].1);
+        if start <= end {
+            result.push((start, end));
+        }
+        if intervals1[i].1 < intervals2[j].1 {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    result
+}
+
+fn complement_intervals(intervals: &[(u128, u128)], max: u128) -> Vec<(u128, u128)> {
+    let mut result = vec![];
+    let mut next_start = Some(0_u128);
+    for (start, end) in intervals {
+        if let Some(next) = next_start {
+            if *start > next {
+                result.push((next, start - 1));
+            }
+        }
+        next_start = iff!(*end >= max, None, Some(end + 1));
+    }
+    if let Some(next) = next_start {
+        result.push((next, max));
+    }
+    result
+}
+
+fn interval_to_masks(is_ipv4: bool, start: u128, end: u128, masks: &mut Vec<IpAddressMask>) {
+    let bits = iff!(is_ipv4, 32_u32, 128_u32);
+    let mut start = start;
+    loop {
+        // largest block aligned at start and not exceeding end
+        let mut block_bits = iff!(start == 0, bits, start.trailing_zeros().min(bits));
+        while block_bits > 0 && start | low_bits_mask(block_bits) > end {
+            block_bits -= 1;
+        }
+        let block_end = start | low_bits_mask(block_bits);
+        masks.push(value_to_ip_address_mask(is_ipv4, start, (bits - block_bits) as u8));
+        if block_end >= end {
+            break;
+        }
+        start = block_end + 1;
+    }
+}
+
+fn low_bits_mask(bits: u32) -> u128 {
+    iff!(bits >= 128, u128::MAX, (1_u128 << bits) - 1)
+}
+
+fn mask_to_interval(ip_address_mask: &IpAddressMask) -> (bool, u128, u128) {
+    match ip_address_mask {
+        IpAddressMask::Ipv4(ipv4, mask) => {
+            let mask = (*mask).min(32);
+            let start = ipv4_to_u32(ipv4) as u128 & (ipv6_mask(mask) >> 96);
+            (true, start, start | low_bits_mask(32 - mask as u32))
+        }
+        IpAddressMask::Ipv6(ipv6, mask) => {
+            let mask = (*mask).min(128);
+            let start = ipv6_to_u128(ipv6) & ipv6_mask(mask);
+            (false, start, start | low_bits_mask(128 - mask as u32))
+        }
+    }
+}
+
+fn range_to_interval(ip_range: &IpRange) -> (bool, u128, u128) {
+    (ip_range.start.is_ipv4(), to_value(&ip_range.start), to_value(&ip_range.end))
+}
+
+// IPv6 interval inside `::ffff:0:0/96` is converted to IPv4 interval
+fn canonicalize_interval((is_ipv4, start, end): (bool, u128, u128)) -> (bool, u128, u128) {
+    if !is_ipv4 && IPV4_MAPPED_START <= start && end <= IPV4_MAPPED_END {
+        (true, start - IPV4_MAPPED_START, end - IPV4_MAPPED_START)
+    } else {
+        (is_ipv4, start, end)
+    }
+}
+
+fn intervals_contain(intervals: &[(u128, u128)], value: u128) -> bool {
+    let index = intervals.partition_point(|(start, _)| *start <= value);
+    index > 0 && intervals[index - 1].1 >= value
+}
+
+fn to_value(ip_address: &IpAddress) -> u128 {
+    match ip_address {
+        IpAddress::Ipv4(ipv4) => ipv4_to_u32(ipv4) as u128,
+        IpAddress::Ipv6(ipv6) => ipv6_to_u128(ipv6),
+    }
+}
+
+#[test]
+fn test_aggregate() {
+    let parse_masks = |masks: &[&str]| masks.iter().map(|m| IpAddressMask::parse(m).unwrap()).collect::<Vec<_>>();
+    let format_masks = |masks: &[IpAddressMask]| masks.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+    assert_eq!(vec!["10.0.0.0/23"], format_masks(&aggregate(&parse_masks(&["10.0.0.0/24", "10.0.1.0/24"]))));
+    assert_eq!(vec!["10.0.0.0/8"], format_masks(&aggregate(&parse_masks(&["10.1.0.0/16", "10.0.0.0/8", "10.2.3.4"]))));
+    assert_eq!(vec!["10.0.1.0/24", "10.0.2.0/24"], format_masks(&aggregate(&parse_masks(&["10.0.1.0/24", "10.0.2.0/24"]))));
+    assert_eq!(vec!["0.0.0.0/0", "::/0"], format_masks(&aggregate(&parse_masks(&["0.0.0.0/1", "128.0.0.0/1", "::/1", "8000::/1"]))));
+    assert_eq!(vec!["1.2.3.4/32", "2001:db8::/31"], format_masks(&aggregate(&parse_masks(&["2001:db9::/32", "1.2.3.4", "2001:db8::/32"]))));
+}
+
+#[test]
+fn test_subtract_intersect_complement() {
+    let parse_masks = |masks: &[&str]| masks.iter().map(|m| IpAddressMask::parse(m).unwrap()).collect::<Vec<_>>();
+    let format_masks = |masks: &[IpAddressMask]| masks.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+    assert_eq!(vec!["10.0.0.0/16", "10.2.0.0/15", "10.4.0.0/14", "10.8.0.0/13", "10.16.0.0/12", "10.32.0.0/11", "10.64.0.0/10", "10.128.0.0/9"],
+               format_masks(&subtract(&parse_masks(&["10.0.0.0/8"]), &parse_masks(&["10.1.0.0/16"]))));
+    assert_eq!(vec!["10.0.0.0/25"], format_masks(&subtract(&parse_masks(&["10.0.0.0/24"]), &parse_masks(&["10.0.0.128/25", "192.168.0.0/16"]))));
+    assert!(subtract(&parse_masks(&["10.0.0.0/24"]), &parse_masks(&["10.0.0.0/8"])).is_empty());
+    assert_eq!(vec!["10.1.0.0/16"], format_masks(&intersect(&parse_masks(&["10.0.0.0/8"]), &parse_masks(&["10.1.0.0/16", "11.0.0.0/8"]))));
+
+    let set = IpRangeSet::from_ip_address_masks(&parse_masks(&["0.0.0.0/1", "::/1"]));
+    assert_eq!(vec!["128.0.0.0/1", "8000::/1"], format_masks(&set.complement().to_ip_address_masks()));
+    assert_eq!(vec!["0.0.0.0/0", "::/0"], format_masks(&IpRangeSet::new().complement().to_ip_address_masks()));
+    assert!(set.union(&set.complement()).complement().is_empty());
+}
+
+#[test]
+fn test_ip_range() {
+    let format_masks = |masks: &[IpAddressMask]| masks.iter().map(|m| m.to_string()).collect::<Vec<_>>();
+    let range = IpRange::parse("10.0.0.1-10.0.0.50").unwrap();
+    assert_eq!("10.0.0.1-10.0.0.50", range.to_string());
+    assert_eq!(vec!["10.0.0.1/32", "10.0.0.2/31", "10.0.0.4/30", "10.0.0.8/29", "10.0.0.16/28", "10.0.0.32/28", "10.0.0.48/31", "10.0.0.50/32"],
+               format_masks(&range.to_ip_address_masks()));
+    assert!(range.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 50])));
+    assert!(!range.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 51])));
+    assert_eq!(vec!["::/0"], format_masks(&IpRange::parse("::-ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff").unwrap().to_ip_address_masks()));
+    assert_eq!(vec!["255.255.255.255/32"], format_masks(&IpRange::parse("255.255.255.255-255.255.255.255").unwrap().to_ip_address_masks()));
+    assert!(IpRange::parse("10.0.0.2-10.0.0.1").is_none());
+    assert!(IpRange::parse("10.0.0.2-::1").is_none());
+    assert_eq!("10.0.0.0-10.0.0.255", IpRange::from_ip_address_mask(&IpAddressMask::parse("10.0.0.7/24").unwrap()).to_string());
+
+    let set = IpRangeSet::from_ip_ranges(&[range, IpRange::parse("10.0.0.51-10.0.0.63").unwrap()]);
+    assert_eq!(vec!["10.0.0.1-10.0.0.63"], set.to_ip_ranges().iter().map(|r| r.to_string()).collect::<Vec<_>>());
+    assert!(set.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 63])));
+    assert!(!set.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 0])));
+    assert!(!set.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 64])));
+}
+
+#[test]
+fn test_ip_range_set_ipv4_mapped() {
+    let mask = |s: &str| IpAddressMask::parse(s).unwrap();
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    let mut set = IpRangeSet::from_ip_address_masks(&[mask("10.0.0.0/8")]);
+    assert!(set.contains_ip_address(&ip("::ffff:10.0.0.1")));
+    set.add_ip_address_mask(&mask("::ffff:192.168.0.0/112"));
+    set.add_ip_range(&IpRange::parse("::ffff:172.16.0.1-::ffff:172.16.0.9").unwrap());
+    assert_eq!(vec!["10.0.0.0-10.255.255.255", "172.16.0.1-172.16.0.9", "192.168.0.0-192.168.255.255"],
+               set.to_ip_ranges().iter().map(|r| r.to_string()).collect::<Vec<_>>());
+    assert!(set.contains_ip_address(&ip("192.168.1.1")));
+    assert!(set.contains_ip_address(&ip("::ffff:172.16.0.9")));
+    assert!(!set.contains_ip_address(&ip("::ffff:172.16.0.10")));
+    let set = IpRangeSet::from_ip_address_masks(&[mask("::/0")]);
+    assert!(set.contains_ip_address(&ip("::ffff:8.8.8.8")));
+    assert!(set.contains_ip_address(&ip("8.8.8.8")));
+    // mask length out of range is clamped
+    assert_eq!("10.0.0.1-10.0.0.1", IpRange::from_ip_address_mask(&IpAddressMask::Ipv4([10, 0, 0, 1], 40)).to_string());
+}
+
+#[test]
+fn test_ip_range_set_incremental_add() {
+    let mask = |s: &str| IpAddressMask::parse(s).unwrap();
+    let mut set = IpRangeSet::new();
+    for m in ["10.0.2.0/24", "10.0.0.0/24", "10.0.4.0/24", "10.0.1.0/24", "10.0.3.0/25", "10.0.3.128/25", "0.0.0.0/32", "255.255.255.255/32"] {
+        set.add_ip_address_mask(&mask(m));
+    }
+    assert_eq!(vec!["0.0.0.0-0.0.0.0", "10.0.0.0-10.0.4.255", "255.255.255.255-255.255.255.255"],
+               set.to_ip_ranges().iter().map(|r| r.to_string()).collect::<Vec<_>>());
+    set.add_ip_address_mask(&mask("0.0.0.0/0"));
+    assert_eq!(set, IpRangeSet::from_ip_address_masks(&[mask("0.0.0.0/0")]));
+}
@@ -0,0 +1,172 @@
+use std::net::SocketAddr;
+
+use crate::util_net::{IpAddress, IpAddressMaskGroup};
+
+/// The header written by trusted proxies, other forwarded headers are client supplied and ignored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardedSource {
+    Forwarded,
+    XForwardedFor,
+    XRealIp,
+}
+
+/// Raw header values, multiple header lines with the same name should be added in order
+#[derive(Debug, Clone, Default)]
+pub struct ForwardedHeaders {
+    // RFC 7239 `Forwarded`
+    pub forwarded: Option<String>,
+    pub x_forwarded_for: Option<String>,
+    pub x_real_ip: Option<String>,
+}
+
+impl ForwardedHeaders {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_forwarded(mut self, forwarded: &str) -> Self {
+        self.forwarded = Some(append_header(self.forwarded.take(), forwarded));
+        self
+    }
+
+    pub fn with_x_forwarded_for(mut self, x_forwarded_for: &str) -> Self {
+        self.x_forwarded_for = Some(append_header(self.x_forwarded_for.take(), x_forwarded_for));
+        self
+    }
+
+    pub fn with_x_real_ip(mut self, x_real_ip: &str) -> Self {
+        self.x_real_ip = Some(x_real_ip.to_string());
+        self
+    }
+
+    /// Forwarded chain from client to the last proxy in header `source`,
+    /// `None` for `unknown`, obfuscated or invalid node
+    pub fn get_forwarded_chain(&self, source: ForwardedSource) -> Vec<Option<IpAddress>> {
+        match source {
+            ForwardedSource::Forwarded => self.forwarded.iter().flat_map(|forwarded| {
+                split_unquoted(forwarded, ',').into_iter().map(|element| {
+                    split_unquoted(element, ';').iter()
+                        .filter_map(|pair| pair.split_once('='))
+                        .find(|(key, _)| key.trim().eq_ignore_ascii_case("for"))
+                        .and_then(|(_, value)| parse_node(value))
+                })
+            }).collect(),
+            ForwardedSource::XForwardedFor => self.x_forwarded_for.iter()
+                .flat_map(|x_forwarded_for| x_forwarded_for.split(',').map(parse_node)).collect(),
+            ForwardedSource::XRealIp => self.x_real_ip.iter().map(|x_real_ip| parse_node(x_real_ip)).collect(),
+        }
+    }
+}
+
+/// Resolve the client IP address, forwarded header `source` is used only when `peer_addr` is a trusted proxy,
+/// `source` must be the header which trusted proxies write, the chain is walked from the right, the first address not in `trusted_proxies` is the client,
+/// when an invalid node is met, the last trusted proxy is returned because nodes before it can be spoofed
+pub fn resolve_client_ip(peer_addr: &SocketAddr, headers: &ForwardedHeaders, source: ForwardedSource,
+                         trusted_proxies: &IpAddressMaskGroup) -> IpAddress {
+    let mut client_ip = IpAddress::from(peer_addr.ip()).to_canonical();
+    if !trusted_proxies.contains_ip_address(&client_ip) {
+        return client_ip;
+    }
+    for node in headers.get_forwarded_chain(source).into_iter().rev() {
+        match node {
+            None => return client_ip,
+            Some(ip) => {
+                client_ip = ip;
+                if !trusted_proxies.contains_ip_address(&client_ip) {
+                    return client_ip;
+                }
+            }
+        }
+    }
+    client_ip
+}
+
+fn append_header(header: Option<String>, value: &str) -> String {
+    match header {
+        None => value.to_string(),
+        Some(header) => format!("{}, {}", header, value),
+    }
+}
+
+// `1.2.3.4`, `1.2.3.4:80`, `2001:db8::1`, `[2001:db8::1]:80` or quoted
+fn parse_node(node: &str) -> Option<IpAddress> {
+    let node = node.trim().trim_matches('"');
+    let ip = if let Some(ipv6_and_port) = node.strip_prefix('[') {
+        IpAddress::parse_ipv6(ipv6_and_port.split_once(']')?.0)?
+    } else if let Some(ip) = IpAddress::parse(node) {
+        ip
+    } else {
+        let (ipv4, port) = node.rsplit_once(':')?;
+        port.parse::<u16>().ok()?;
+        IpAddress::parse_ipv4(ipv4)?
+    };
+    Some(ip.to_canonical())
+}
+
+// split by `separator` which is not in quoted string
+fn split_unquoted(s: &str, separator: char) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut is_quoted = false;
+    let mut is_escaped = false;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            _ if is_escaped => is_escaped = false,
+            '\\' if is_quoted => is_escaped = true,
+            '"' => is_quoted = !is_quoted,
+            _ if c == separator && !is_quoted => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+#[test]
+fn test_resolve_client_ip_x_forwarded_for() {
+    let trusted_proxies = IpAddressMaskGroup::parse(&["10.0.0.0/8".to_string(), "fd00::/8".to_string()]);
+    let peer = "10.0.0.1:4000".parse::<SocketAddr>().unwrap();
+    let resolve = |peer: &SocketAddr, headers: &ForwardedHeaders| {
+        resolve_client_ip(peer, headers, ForwardedSource::XForwardedFor, &trusted_proxies).to_string()
+    };
+
+    let headers = ForwardedHeaders::new().with_x_forwarded_for("203.0.113.9, 198.51.100.1, 10.0.0.2");
+    assert_eq!("198.51.100.1", resolve(&peer, &headers));
+    // untrusted peer, headers are ignored
+    assert_eq!("192.0.2.1", resolve(&"192.0.2.1:1".parse().unwrap(), &headers));
+    assert_eq!("10.0.0.1", resolve(&"[::ffff:10.0.0.1]:1".parse().unwrap(), &ForwardedHeaders::new()));
+
+    let headers = ForwardedHeaders::new().with_x_forwarded_for("10.0.0.3").with_x_forwarded_for("10.0.0.2");
+    assert_eq!("10.0.0.3", resolve(&peer, &headers));
+    let headers = ForwardedHeaders::new().with_x_forwarded_for("203.0.113.9, garbage, 10.0.0.2");
+    assert_eq!("10.0.0.2", resolve(&peer, &headers));
+    let headers = ForwardedHeaders::new().with_x_forwarded_for("[2001:db8::1]:5000, fd00::1");
+    assert_eq!("2001:db8::1", resolve(&peer, &headers));
+    // client supplied `Forwarded` through an XFF-only proxy is ignored
+    let headers = ForwardedHeaders::new().with_forwarded("for=10.0.0.9, for=127.0.0.1").with_x_forwarded_for("203.0.113.9");
+    assert_eq!("203.0.113.9", resolve(&peer, &headers));
+    let headers = ForwardedHeaders::new().with_x_real_ip("203.0.113.9");
+    assert_eq!("10.0.0.1", resolve(&peer, &headers));
+    assert_eq!("203.0.113.9", resolve_client_ip(&peer, &headers, ForwardedSource::XRealIp, &trusted_proxies).to_string());
+}
+
+#[test]
+fn test_resolve_client_ip_forwarded() {
+    let trusted_proxies = IpAddressMaskGroup::parse(&["10.0.0.0/8".to_string()]);
+    let peer = "10.0.0.1:4000".parse::<SocketAddr>().unwrap();
+    let resolve = |headers: &ForwardedHeaders| resolve_client_ip(&peer, headers, ForwardedSource::Forwarded, &trusted_proxies).to_string();
+
+    let headers = ForwardedHeaders::new()
+        .with_forwarded(r#"for=192.0.2.60;proto=http;by=203.0.113.43, For="[2001:db8:cafe::17]:4711""#)
+        .with_forwarded("for=10.0.0.5")
+        .with_x_forwarded_for("1.1.1.1");
+    assert_eq!("2001:db8:cafe::17", resolve(&headers));
+    let headers = ForwardedHeaders::new().with_forwarded(r#"for="_hidden", for="10.0.0.7:80";by="a,b""#);
+    assert_eq!("10.0.0.7", resolve(&headers));
+    let headers = ForwardedHeaders::new().with_forwarded("for=unknown");
+    assert_eq!("10.0.0.1", resolve(&headers));
+    assert_eq!(vec!["a", "\"b,c\"", "d"], split_unquoted("a,\"b,c\",d", ','));
+}
@@ -0,0 +1,244 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use crate::util_net::{ipv4_to_u32, ipv6_to_u128, IpAddress, IpAddressMask};
+use crate::util_net_cidr::{IpRange, IpRangeSet};
+use crate::{opt_result, simple_error, XResult};
+
+const FROZEN_IP_SET_MAGIC: &[u8; 4] = b"FIPS";
+const FROZEN_IP_SET_VERSION: u8 = 1;
+// magic(4) + version(1) + reserved(3) + IPv4 count(4) + IPv6 count(4)
+const HEADER_SIZE: usize = 16;
+// start(4) + end(4)
+const IPV4_ENTRY_SIZE: usize = 8;
+// start(16) + end(16)
+const IPV6_ENTRY_SIZE: usize = 32;
+
+/// Immutable address set for large blocklists, stored as sorted non-overlapping inclusive intervals.
+///
+/// The in-memory layout is the file layout (all little endian), so loading only validates entries without copying:
+/// header, IPv4 `(start: u32, end: u32)` entries, then IPv6 `(start: u128, end: u128)` entries
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrozenIpSet {
+    bytes: Vec<u8>,
+    ipv4_count: usize,
+    ipv6_count: usize,
+}
+
+impl FrozenIpSet {
+    pub fn from_ip_range_set(ip_range_set: &IpRangeSet) -> Self {
+        let (ipv4_intervals, ipv6_intervals) = (ip_range_set.get_ipv4_intervals(), ip_range_set.get_ipv6_intervals());
+        let mut bytes = Vec::with_capacity(HEADER_SIZE
+            + ipv4_intervals.len() * IPV4_ENTRY_SIZE + ipv6_intervals.len() * IPV6_ENTRY_SIZE);
+        bytes.extend_from_slice(FROZEN_IP_SET_MAGIC);
+        bytes.extend_from_slice(&[FROZEN_IP_SET_VERSION, 0, 0, 0]);
+        bytes.extend_from_slice(&(ipv4_intervals.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&(ipv6_intervals.len() as u32).to_le_bytes());
+        for (start, end) in ipv4_intervals {
+            bytes.extend_from_slice(&(*start as u32).to_le_bytes());
+            bytes.extend_from_slice(&(*end as u32).to_le_bytes());
+        }
+        for (start, end) in ipv6_intervals {
+            bytes.extend_from_slice(&start.to_le_bytes());
+            bytes.extend_from_slice(&end.to_le_bytes());
+        }
+        Self { bytes, ipv4_count: ipv4_intervals.len(), ipv6_count: ipv6_intervals.len() }
+    }
+
+    pub fn from_ip_address_masks(ip_address_masks: &[IpAddressMask]) -> Self {
+        Self::from_ip_range_set(&IpRangeSet::from_ip_address_masks(ip_address_masks))
+    }
+
+    pub fn from_ip_ranges(ip_ranges: &[IpRange]) -> Self {
+        Self::from_ip_range_set(&IpRangeSet::from_ip_ranges(ip_ranges))
+    }
+
+    /// Bytes are untrusted, header, size and entry order are validated
+    pub fn from_bytes(bytes: Vec<u8>) -> XResult<Self> {
+        if bytes.len() < HEADER_SIZE || &bytes[0..4] != FROZEN_IP_SET_MAGIC {
+            return simple_error!("Frozen IP set header is invalid");
+        }
+        if bytes[4] != FROZEN_IP_SET_VERSION {
+            return simple_error!("Frozen IP set version: {} not supported", bytes[4]);
+        }
+        let ipv4_count = read_u32(&bytes, 8) as usize;
+        let ipv6_count = read_u32(&bytes, 12) as usize;
+        let expected_len = ipv4_count.checked_mul(IPV4_ENTRY_SIZE)
+            .and_then(|ipv4_len| ipv6_count.checked_mul(IPV6_ENTRY_SIZE).and_then(|ipv6_len| ipv4_len.checked_add(ipv6_len)))
+            .and_then(|len| len.checked_add(HEADER_SIZE));
+        if expected_len != Some(bytes.len()) {
+            return simple_error!("Frozen IP set size mismatch, IPv4 count: {}, IPv6 count: {}, actual size: {}",
+                ipv4_count, ipv6_count, bytes.len());
+        }
+        let set = Self { bytes, ipv4_count, ipv6_count };
+        if !is_sorted_intervals(set.ipv4_count, |i| set.get_ipv4_interval(i)) {
+            return simple_error!("Frozen IP set IPv4 intervals are not sorted or overlapped");
+        }
+        if !is_sorted_intervals(set.ipv6_count, |i| set.get_ipv6_interval(i)) {
+            return simple_error!("Frozen IP set IPv6 intervals are not sorted or overlapped");
+        }
+        Ok(set)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        let path = path.as_ref();
+        let bytes = opt_result!(fs::read(path), "Read frozen IP set file: {}, failed: {}", path.display());
+        Self::from_bytes(bytes)
+    }
+
+    pub fn write_file<P: AsRef<Path>>(&self, path: P) -> XResult<()> {
+        let path = path.as_ref();
+        opt_result!(fs::write(path, &self.bytes), "Write frozen IP set file: {}, failed: {}", path.display());
+        Ok(())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Interval count
+    pub fn len(&self) -> usize {
+        self.ipv4_count + self.ipv6_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // IPv4-mapped IPv6 address is matched as IPv4 address
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        match ip_address.to_canonical() {
+            IpAddress::Ipv4(ipv4) => contains_value(self.ipv4_count, |i| self.get_ipv4_interval(i), ipv4_to_u32(&ipv4)),
+            IpAddress::Ipv6(ipv6) => contains_value(self.ipv6_count, |i| self.get_ipv6_interval(i), ipv6_to_u128(&ipv6)),
+        }
+    }
+
+    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
+        self.contains_ip_address(&socket_addr.ip().into())
+    }
+
+    pub fn to_ip_range_set(&self) -> IpRangeSet {
+        let to_ip_range = |start: IpAddress, end: IpAddress| IpRange { start, end };
+        let mut ip_ranges = Vec::with_capacity(self.len());
+        for (start, end) in (0..self.ipv4_count).map(|i| self.get_ipv4_interval(i)) {
+            ip_ranges.push(to_ip_range(IpAddress::Ipv4(start.to_be_bytes()), IpAddress::Ipv4(end.to_be_bytes())));
+        }
+        for (start, end) in (0..self.ipv6_count).map(|i| self.get_ipv6_interval(i)) {
+            ip_ranges.push(to_ip_range(IpAddress::Ipv6(start.to_be_bytes()), IpAddress::Ipv6(end.to_be_bytes())));
+        }
+        IpRangeSet::from_ip_ranges(&ip_ranges)
+    }
+
+    fn get_ipv4_interval(&self, index: usize) -> (u32, u32) {
+        let offset = HEADER_SIZE + index * IPV4_ENTRY_SIZE;
+        (read_u32(&self.bytes, offset), read_u32(&self.bytes, offset + 4))
+    }
+
+    fn get_ipv6_interval(&self, index: usize) -> (u128, u128) {
+        let offset = HEADER_SIZE + self.ipv4_count * IPV4_ENTRY_SIZE + index * IPV6_ENTRY_SIZE;
+        (read_u128(&self.bytes, offset), read_u128(&self.bytes, offset + 16))
+    }
+}
+
+// binary search the last interval which starts at or before `value`
+fn contains_value<T: Ord + Copy>(count: usize, get_interval: impl Fn(usize) -> (T, T), value: T) -> bool {
+    let (mut low, mut high) = (0, count);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if get_interval(mid).0 <= value {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low > 0 && get_interval(low - 1).1 >= value
+}
+
+// every interval has start <= end and starts after the previous one ends
+fn is_sorted_intervals<T: Ord + Copy>(count: usize, get_interval: impl Fn(usize) -> (T, T)) -> bool {
+    let mut previous_end = None;
+    for (start, end) in (0..count).map(get_interval) {
+        if start > end || previous_end.map(|previous_end| start <= previous_end).unwrap_or(false) {
+            return false;
+        }
+        previous_end = Some(end);
+    }
+    true
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buf = [0_u8; 4];
+    buf.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_le_bytes(buf)
+}
+
+fn read_u128(bytes: &[u8], offset: usize) -> u128 {
+    let mut buf = [0_u8; 16];
+    buf.copy_from_slice(&bytes[offset..offset + 16]);
+    u128::from_le_bytes(buf)
+}
+
+#[test]
+fn test_frozen_ip_set() {
+    let parse_masks = |masks: &[&str]| masks.iter().map(|m| IpAddressMask::parse(m).unwrap()).collect::<Vec<_>>();
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    let set = FrozenIpSet::from_ip_address_masks(&parse_masks(&["10.0.0.0/24", "10.0.1.0/24", "1.2.3.4", "192.168.0.0/16", "2001:db8::/32"]));
+    assert_eq!(4, set.len());
+    assert_eq!(HEADER_SIZE + 3 * IPV4_ENTRY_SIZE + IPV6_ENTRY_SIZE, set.as_bytes().len());
+    for contained in ["10.0.0.0", "10.0.1.255", "1.2.3.4", "192.168.255.255", "::ffff:10.0.0.1", "2001:db8:ffff::1"] {
+        assert!(set.contains_ip_address(&ip(contained)), "{}", contained);
+    }
+    for not_contained in ["0.0.0.0", "10.0.2.0", "1.2.3.3", "1.2.3.5", "255.255.255.255", "::", "2001:db9::"] {
+        assert!(!set.contains_ip_address(&ip(not_contained)), "{}", not_contained);
+    }
+    assert!(set.is_matches(&"192.168.1.1:443".parse().unwrap()));
+    assert!(!FrozenIpSet::from_ip_address_masks(&[]).contains_ip_address(&ip("1.1.1.1")));
+    assert!(FrozenIpSet::from_ip_address_masks(&[]).is_empty());
+    assert!(FrozenIpSet::from_ip_address_masks(&parse_masks(&["0.0.0.0/0"])).contains_ip_address(&ip("255.255.255.255")));
+    assert_eq!(vec!["1.2.3.4/32", "10.0.0.0/23", "192.168.0.0/16", "2001:db8::/32"],
+               set.to_ip_range_set().to_ip_address_masks().iter().map(|m| m.to_string()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_frozen_ip_set_file() {
+    let set = FrozenIpSet::from_ip_ranges(&[IpRange::parse("10.0.0.1-10.0.0.50").unwrap(), IpRange::parse("::1-::2").unwrap()]);
+    let file = std::env::temp_dir().join(format!("rust_util_test_frozen_{}.bin", std::process::id()));
+    set.write_file(&file).unwrap();
+    let loaded = FrozenIpSet::load_file(&file).unwrap();
+    fs::remove_file(&file).ok();
+    assert_eq!(set, loaded);
+    assert!(loaded.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 50])));
+    assert!(!loaded.contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 51])));
+
+    let bytes = set.as_bytes().to_vec();
+    assert!(FrozenIpSet::from_bytes(bytes[..bytes.len() - 1].to_vec()).is_err());
+    assert!(FrozenIpSet::from_bytes(b"FIPX".to_vec()).is_err());
+    let mut unsupported_version = bytes.clone();
+    unsupported_version[4] = 2;
+    assert!(FrozenIpSet::from_bytes(unsupported_version).is_err());
+    let mut huge_count = bytes.clone();
+    huge_count[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    huge_count[12..16].copy_from_slice(&u32::MAX.to_le_bytes());
+    assert!(FrozenIpSet::from_bytes(huge_count).is_err());
+}
+
+#[test]
+fn test_frozen_ip_set_invalid_intervals() {
+    let build = |ipv4_intervals: &[(u32, u32)]| {
+        let mut bytes = FrozenIpSet::from_ip_address_masks(&[]).as_bytes().to_vec();
+        bytes[8..12].copy_from_slice(&(ipv4_intervals.len() as u32).to_le_bytes());
+        for (start, end) in ipv4_intervals {
+            bytes.extend_from_slice(&start.to_le_bytes());
+            bytes.extend_from_slice(&end.to_le_bytes());
+        }
+        FrozenIpSet::from_bytes(bytes)
+    };
+    assert_eq!(2, build(&[(1, 2), (3, 4)]).unwrap().len());
+    assert!(build(&[(2, 1)]).is_err());
+    assert!(build(&[(3, 4), (1, 2)]).is_err());
+    assert!(build(&[(1, 3), (3, 4)]).is_err());
+    let mut bytes = FrozenIpSet::from_ip_ranges(&[IpRange::parse("::1-::2").unwrap(), IpRange::parse("::5-::6").unwrap()]).as_bytes().to_vec();
+    bytes[HEADER_SIZE + IPV6_ENTRY_SIZE] = 2;
+    assert!(FrozenIpSet::from_bytes(bytes).is_err());
+}
@@ -0,0 +1,201 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::util_net::{IpAddress, IpAddressMask, IpAddressMaskGroup};
+use crate::{iff, XResult};
+
+#[derive(Debug, Clone)]
+pub struct NetworkInterface {
+    pub name: String,
+    pub is_up: bool,
+    pub is_running: bool,
+    pub is_loopback: bool,
+    pub is_multicast: bool,
+    // addresses with prefix length, e.g. `192.168.1.10/24`
+    pub ip_address_masks: Vec<IpAddressMask>,
+    // `None` when not available, e.g. loopback
+    pub mac_address: Option<[u8; 6]>,
+}
+
+impl NetworkInterface {
+    pub fn get_mac_address_string(&self) -> Option<String> {
+        self.mac_address.map(|mac| mac.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(":"))
+    }
+
+    pub fn get_ip_addresses(&self) -> Vec<IpAddress> {
+        self.ip_address_masks.iter().map(IpAddressMask::ip_address).collect()
+    }
+
+    /// Is `ip_address` in any subnet of this interface
+    pub fn is_on_subnet(&self, ip_address: &IpAddress) -> bool {
+        self.ip_address_masks.iter().any(|m| m.contains_ip_address(ip_address))
+    }
+}
+
+impl Display for NetworkInterface {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        let mut flags = vec![];
+        if self.is_up { flags.push("UP"); }
+        if self.is_running { flags.push("RUNNING"); }
+        if self.is_loopback { flags.push("LOOPBACK"); }
+        if self.is_multicast { flags.push("MULTICAST"); }
+        write!(f, "{}: <{}>", self.name, flags.join(","))?;
+        if let Some(mac_address) = self.get_mac_address_string() {
+            write!(f, " ether {}", mac_address)?;
+        }
+        for ip_address_mask in &self.ip_address_masks {
+            write!(f, " {}", ip_address_mask)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn get_network_interface(name: &str) -> XResult<Option<NetworkInterface>> {
+    Ok(list_network_interfaces()?.into_iter().find(|i| i.name == name))
+}
+
+/// Does any local interface address in `ip_address_mask_group`, e.g. "am I on the corporate subnet"
+pub fn has_local_address_in(ip_address_mask_group: &IpAddressMaskGroup) -> XResult<bool> {
+    Ok(list_network_interfaces()?.iter().filter(|i| i.is_up)
+        .flat_map(|i| i.get_ip_addresses())
+        .any(|ip_address| ip_address_mask_group.contains_ip_address(&ip_address)))
+}
+
+/// List network interfaces by `getifaddrs`, in the order of first appearance
+#[cfg(unix)]
+pub fn list_network_interfaces() -> XResult<Vec<NetworkInterface>> {
+    use std::ffi::CStr;
+    let mut ifaddrs: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifaddrs) } != 0 {
+        return crate::simple_error!("Call getifaddrs failed: {}", std::io::Error::last_os_error());
+    }
+    let mut interfaces: Vec<NetworkInterface> = vec![];
+    let mut current = ifaddrs;
+    while !current.is_null() {
+        let ifaddr = unsafe { &*current };
+        current = ifaddr.ifa_next;
+        let name = unsafe { CStr::from_ptr(ifaddr.ifa_name) }.to_string_lossy().to_string();
+        let index = match interfaces.iter().position(|i| i.name == name) {
+            Some(index) => index,
+            None => {
+                let flags = ifaddr.ifa_flags as libc::c_int;
+                interfaces.push(NetworkInterface {
+                    name,
+                    is_up: flags & libc::IFF_UP != 0,
+                    is_running: flags & libc::IFF_RUNNING != 0,
+                    is_loopback: flags & libc::IFF_LOOPBACK != 0,
+                    is_multicast: flags & libc::IFF_MULTICAST != 0,
+                    ip_address_masks: vec![],
+                    mac_address: None,
+                });
+                interfaces.len() - 1
+            }
+        };
+        if ifaddr.ifa_addr.is_null() {
+            continue;
+        }
+        let interface = &mut interfaces[index];
+        match unsafe { (*ifaddr.ifa_addr).sa_family } as libc::c_int {
+            libc::AF_INET => {
+                let ipv4 = unsafe { get_ipv4_octets(ifaddr.ifa_addr) };
+                let mask_len = iff!(ifaddr.ifa_netmask.is_null(), 32,
+                    u32::from_be_bytes(unsafe { get_ipv4_octets(ifaddr.ifa_netmask) }).count_ones() as u8);
+                interface.ip_address_masks.push(IpAddressMask::Ipv4(ipv4, mask_len));
+            }
+            libc::AF_INET6 => {
+                let ipv6 = unsafe { get_ipv6_octets(ifaddr.ifa_addr) };
+                let mask_len = iff!(ifaddr.ifa_netmask.is_null(), 128,
+                    u128::from_be_bytes(unsafe { get_ipv6_octets(ifaddr.ifa_netmask) }).count_ones() as u8);
+                interface.ip_address_masks.push(IpAddressMask::Ipv6(ipv6, mask_len));
+            }
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            libc::AF_PACKET => {
+                let sockaddr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_ll) };
+                if sockaddr.sll_halen == 6 {
+                    interface.mac_address = get_mac_address(&sockaddr.sll_addr[..6]);
+                }
+            }
+            #[cfg(target_vendor = "apple")]
+            libc::AF_LINK => {
+                let sockaddr = unsafe { &*(ifaddr.ifa_addr as *const libc::sockaddr_dl) };
+                if sockaddr.sdl_alen == 6 {
+                    // link address follows the interface name in `sdl_data`
+                    let data = unsafe {
+                        std::slice::from_raw_parts((sockaddr.sdl_data.as_ptr() as *const u8).add(sockaddr.sdl_nlen as usize), 6)
+                    };
+                    interface.mac_address = get_mac_address(data);
+                }
+            }
+            _ => {}
+        }
+    }
+    unsafe { libc::freeifaddrs(ifaddrs) };
+    Ok(interfaces)
+}
+
+#[cfg(not(unix))]
+pub fn list_network_interfaces() -> XResult<Vec<NetworkInterface>> {
+    crate::simple_error!("List network interfaces is not supported on this platform")
+}
+
+#[cfg(unix)]
+unsafe fn get_ipv4_octets(sockaddr: *const libc::sockaddr) -> [u8; 4] {
+    let sockaddr = &*(sockaddr as *const libc::sockaddr_in);
+    sockaddr.sin_addr.s_addr.to_ne_bytes()
+}
+
+#[cfg(unix)]
+unsafe fn get_ipv6_octets(sockaddr: *const libc::sockaddr) -> [u8; 16] {
+    let sockaddr = &*(sockaddr as *const libc::sockaddr_in6);
+    sockaddr.sin6_addr.s6_addr
+}
+
+// all zero address is treated as no MAC address
+#[cfg(unix)]
+fn get_mac_address(data: &[u8]) -> Option<[u8; 6]> {
+    let mut mac_address = [0_u8; 6];
+    mac_address.copy_from_slice(&data[..6]);
+    iff!(mac_address == [0; 6], None, Some(mac_address))
+}
+
+#[test]
+fn test_list_network_interfaces() {
+    let interfaces = list_network_interfaces().unwrap();
+    for interface in &interfaces {
+        assert!(!interface.name.is_empty());
+        assert_eq!(interface.name, get_network_interface(&interface.name).unwrap().unwrap().name);
+        for ip_address_mask in &interface.ip_address_masks {
+            assert!(ip_address_mask.mask_len() <= iff!(ip_address_mask.ip_address().is_ipv4(), 32, 128), "{}", ip_address_mask);
+            assert!(interface.is_on_subnet(&ip_address_mask.ip_address()), "{}", interface);
+        }
+    }
+    // loopback interface may not exist in some sandboxes
+    if let Some(loopback) = interfaces.iter().find(|i| i.is_loopback) {
+        assert!(loopback.ip_address_masks.iter().any(|m| m.ip_address().is_loopback()), "{}", loopback);
+        assert!(loopback.mac_address.is_none(), "{}", loopback);
+        assert!(has_local_address_in(&IpAddressMaskGroup::parse(&["127.0.0.0/8".to_string(), "::1/128".to_string()])).unwrap());
+    }
+    assert!(get_network_interface("not-exists-interface").unwrap().is_none());
+}
+
+// depends on host network config: no 198.18.0.0/15 (benchmarking) address
+#[test]
+#[ignore]
+fn test_has_no_benchmarking_address() {
+    assert!(!has_local_address_in(&IpAddressMaskGroup::parse(&["198.18.0.0/15".to_string()])).unwrap());
+}
+
+#[test]
+fn test_mac_address_string() {
+    let interface = NetworkInterface {
+        name: "eth0".to_string(),
+        is_up: true,
+        is_running: true,
+        is_loopback: false,
+        is_multicast: true,
+        ip_address_masks: vec![IpAddressMask::Ipv4([192, 168, 1, 10], 24)],
+        mac_address: Some([0x02, 0x42, 0xac, 0x11, 0x00, 0x02]),
+    };
+    assert_eq!(Some("02:42:ac:11:00:02".to_string()), interface.get_mac_address_string());
+    assert_eq!("eth0: <UP,RUNNING,MULTICAST> ether 02:42:ac:11:00:02 192.168.1.10/24", interface.to_string());
+    assert!(interface.is_on_subnet(&IpAddress::Ipv4([192, 168, 1, 1])));
+}
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::fmt::{self, Display, Formatter};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+use crate::util_net::{IpAddress, IpAddressMask, IpAddressMaskGroup, IpAndIpMaskMatcher};
+use crate::{opt_result, simple_error, util_msg, XResult};
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+#[derive(Debug, Clone)]
+pub struct IpListEntryError {
+    pub file: String,
+    // line number starts from 1
+    pub line: usize,
+    pub entry: String,
+    pub message: String,
+}
+
+impl Display for IpListEntryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}:{}: {}, entry: {}", self.file, self.line, self.message, self.entry)
+    }
+}
+
+/// IP list loaded from text, one address, CIDR or range per line,
+/// `#` starts a comment, `include <file>` includes another file (relative to the current file)
+pub struct IpList {
+    pub ip_address_masks: Vec<IpAddressMask>,
+    // invalid entries are skipped and recorded here
+    pub errors: Vec<IpListEntryError>,
+    // loaded files and their (modified time, length)
+    sources: Vec<(PathBuf, Option<(SystemTime, u64)>)>,
+    matcher: IpAndIpMaskMatcher,
+}
+
+impl IpList {
+    pub fn load_file<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        let mut loader = IpListLoader::default();
+        loader.load_file(path.as_ref(), 0)?;
+        Ok(loader.into_ip_list())
+    }
+
+    /// Include directives in reader are relative to current work dir
+    pub fn load_reader<R: BufRead>(reader: R, name: &str) -> XResult<Self> {
+        let mut loader = IpListLoader::default();
+        loader.load_reader(reader, name, Path::new("."), 0)?;
+        Ok(loader.into_ip_list())
+    }
+
+    /// Fails when any entry is invalid
+    pub fn load_file_strict<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        Self::load_file(path)?.ensure_no_errors()
+    }
+
+    pub fn ensure_no_errors(self) -> XResult<Self> {
+        if self.errors.is_empty() {
+            return Ok(self);
+        }
+        simple_error!("Load IP list with {} invalid entries: {}", self.errors.len(),
+            self.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ip_address_masks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ip_address_masks.is_empty()
+    }
+
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        self.matcher.contains_ip_address(ip_address)
+    }
+
+    pub fn is_matches(&self, socket_addr: &SocketAddr) -> bool {
+        self.contains_ip_address(&socket_addr.ip().into())
+    }
+
+    pub fn to_ip_address_mask_group(&self) -> IpAddressMaskGroup {
+        IpAddressMaskGroup { ip_address_mask_group: self.ip_address_masks.clone() }
+    }
+
+    pub fn to_ip_and_ip_mask_matcher(&self) -> IpAndIpMaskMatcher {
+        build_matcher(&self.ip_address_masks)
+    }
+
+    fn is_modified(&self) -> bool {
+        self.sources.iter().any(|(path, modified)| get_modified(path) != *modified)
+    }
+}
+
+#[derive(Default)]
+struct IpListLoader {
+    ip_address_masks: Vec<IpAddressMask>,
+    errors: Vec<IpListEntryError>,
+    sources: Vec<(PathBuf, Option<(SystemTime, u64)>)>,
+    loading_files: HashSet<PathBuf>,
+}
+
+impl IpListLoader {
+    fn load_file(&mut self, path: &Path, depth: usize) -> XResult<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return simple_error!("Include too deep: {}", path.display());
+        }
+        let canonical_path = opt_result!(path.canonicalize(), "Open IP list file: {}, failed: {}", path.display());
+        if !self.loading_files.insert(canonical_path.clone()) {
+            return simple_error!("Include cycle detected: {}", path.display());
+        }
+        // get modified time before read, so changes during reading will trigger another reload
+        self.sources.push((canonical_path.clone(), get_modified(&canonical_path)));
+        let file = opt_result!(File::open(&canonical_path), "Open IP list file: {}, failed: {}", path.display());
+        let base_dir = canonical_path.parent().map(Path::to_path_buf).unwrap_or_else(|| PathBuf::from("."));
+        let result = self.load_reader(BufReader::new(file), &path.display().to_string(), &base_dir, depth);
+        self.loading_files.remove(&canonical_path);
+        result
+    }
+
+    fn load_reader<R: BufRead>(&mut self, reader: R, name: &str, base_dir: &Path, depth: usize) -> XResult<()> {
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = opt_result!(line, "Read IP list: {}, failed: {}", name);
+            let entry = line.split('#').next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            if let Some(include_file) = entry.strip_prefix("include ") {
+                let include_path = base_dir.join(include_file.trim());
+                opt_result!(self.load_file(&include_path, depth + 1), "Include at {}:{} failed: {}", name, line_index + 1);
+                continue;
+            }
+            match IpAddressMask::parse_masks(entry) {
+                Ok(ip_address_masks) => self.ip_address_masks.extend(ip_address_masks),
+                Err(e) => self.errors.push(IpListEntryError {
+                    file: name.to_string(),
+                    line: line_index + 1,
+                    entry: entry.to_string(),
+                    message: e.to_string(),
+                }),
+            }
+        }
+        Ok(())
+    }
+
+    fn into_ip_list(self) -> IpList {
+        let matcher = build_matcher(&self.ip_address_masks);
+        IpList { ip_address_masks: self.ip_address_masks, errors: self.errors, sources: self.sources, matcher }
+    }
+}
+
+/// Keeps the IP list loaded from file, and swaps in the reloaded list when file (or included files) modified
+pub struct IpListWatcher {
+    path: PathBuf,
+    ip_list: RwLock<Arc<IpList>>,
+    is_stopped: AtomicBool,
+}
+
+impl IpListWatcher {
+    pub fn new<P: AsRef<Path>>(path: P) -> XResult<Arc<Self>> {
+        let path = path.as_ref().to_path_buf();
+        let ip_list = IpList::load_file(&path)?;
+        print_entry_errors(&ip_list);
+        Ok(Arc::new(Self { path, ip_list: RwLock::new(Arc::new(ip_list)), is_stopped: AtomicBool::new(false) }))
+    }
+
+    /// Current IP list, keeps unchanged even when reloaded
+    pub fn get(&self) -> Arc<IpList> {
+        self.ip_list.read().unwrap().clone()
+    }
+
+    /// Returns true when reloaded, when reload failed the old list is kept
+    pub fn reload_if_modified(&self) -> XResult<bool> {
+        if !self.get().is_modified() {
+            return Ok(false);
+        }
+        let ip_list = IpList::load_file(&self.path)?;
+        print_entry_errors(&ip_list);
+        util_msg::print_info(&format!("IP list reloaded: {}, entries: {}", self.path.display(), ip_list.len()));
+        *self.ip_list.write().unwrap() = Arc::new(ip_list);
+        Ok(true)
+    }
+
+    /// Check modification in background thread every `interval`, until `stop()`
+    pub fn watch(self: &Arc<Self>, interval: Duration) -> JoinHandle<()> {
+        let watcher = self.clone();
+        thread::spawn(move || {
+            while !watcher.is_stopped.load(Ordering::SeqCst) {
+                thread::sleep(interval);
+                if let Err(e) = watcher.reload_if_modified() {
+                    util_msg::print_warn(&format!("Reload IP list: {}, failed: {}", watcher.path.display(), e));
+                }
+            }
+        })
+    }
+
+    pub fn stop(&self) {
+        self.is_stopped.store(true, Ordering::SeqCst);
+    }
+}
+
+fn print_entry_errors(ip_list: &IpList) {
+    for error in &ip_list.errors {
+        util_msg::print_warn(&format!("Invalid IP list entry: {}", error));
+    }
+}
+
+fn build_matcher(ip_address_masks: &[IpAddressMask]) -> IpAndIpMaskMatcher {
+    let mut matcher = IpAndIpMaskMatcher::new();
+    for ip_address_mask in ip_address_masks {
+        matcher.add_ip_address_mask(&ip_address_mask.ip_address(), ip_address_mask.mask_len());
+    }
+    matcher
+}
+
+fn get_modified(path: &Path) -> Option<(SystemTime, u64)> {
+    fs::metadata(path).ok().and_then(|m| m.modified().ok().map(|t| (t, m.len())))
+}
+
+#[test]
+fn test_ip_list_load_reader() {
+    let ip_list = IpList::load_reader(r##"
+# blocklist
+10.0.0.0/8   # private
+192.168.1.1-192.168.1.2
+
+bad-entry
+2001:db8::/32
+10.0.0.9-10.0.0.1
+"##.as_bytes(), "test.txt").unwrap();
+    assert_eq!(4, ip_list.len());
+    assert_eq!(vec!["test.txt:6: Invalid IPv4 address: bad-entry, entry: bad-entry",
+                    "test.txt:8: Invalid range: 10.0.0.9-10.0.0.1, entry: 10.0.0.9-10.0.0.1"],
+               ip_list.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>());
+    assert!(ip_list.contains_ip_address(&IpAddress::Ipv4([10, 1, 2, 3])));
+    assert!(ip_list.contains_ip_address(&IpAddress::Ipv4([192, 168, 1, 2])));
+    assert!(!ip_list.contains_ip_address(&IpAddress::Ipv4([192, 168, 1, 3])));
+    assert!(ip_list.is_matches(&"[2001:db8::1]:80".parse().unwrap()));
+    assert!(ip_list.to_ip_and_ip_mask_matcher().contains_ip_address(&IpAddress::Ipv4([10, 0, 0, 1])));
+    assert_eq!(4, ip_list.to_ip_address_mask_group().ip_address_mask_group.len());
+    assert!(ip_list.ensure_no_errors().is_err());
+}
+
+#[test]
+fn test_ip_list_load_file_and_watch() {
+    let dir = std::env::temp_dir().join(format!("rust_util_ip_list_{}_{}", std::process::id(), crate::util_time::get_current_millis()));
+    fs::create_dir_all(&dir).unwrap();
+    let main_file = dir.join("main.txt");
+    fs::write(&main_file, "10.0.0.0/8\ninclude sub.txt\n").unwrap();
+    fs::write(dir.join("sub.txt"), "192.168.0.0/16\n").unwrap();
+    fs::write(dir.join("cycle.txt"), "include cycle.txt\n").unwrap();
+
+    let watcher = IpListWatcher::new(&main_file).unwrap();
+    let ip_list = watcher.get();
+    assert_eq!(2, ip_list.len());
+    assert!(ip_list.contains_ip_address(&IpAddress::Ipv4([192, 168, 1, 1])));
+    assert!(!watcher.reload_if_modified().unwrap());
+
+    fs::write(dir.join("sub.txt"), "172.16.0.0/12\n1.1.1.1\n").unwrap();
+    assert!(watcher.reload_if_modified().unwrap());
+    assert_eq!(3, watcher.get().len());
+    assert!(!watcher.get().contains_ip_address(&IpAddress::Ipv4([192, 168, 1, 1])));
+    assert!(watcher.get().contains_ip_address(&IpAddress::Ipv4([172, 16, 1, 1])));
+    // old snapshot is unchanged
+    assert!(ip_list.contains_ip_address(&IpAddress::Ipv4([192, 168, 1, 1])));
+
+    assert!(IpList::load_file(dir.join("cycle.txt")).is_err());
+    assert!(IpList::load_file(dir.join("not_exists.txt")).is_err());
+    fs::remove_dir_all(&dir).ok();
+}
@@ -0,0 +1,246 @@
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+use std::str::FromStr;
+
+use crate::util_net::{IpAddressError, IpAddressErrorKind};
+use crate::{iff, opt_result, simple_error, XResult};
+
+/// Port ranges like `80,443,8080-8090`, ranges are inclusive and kept in the given order,
+/// port 0 is not allowed (binding port 0 gets a random port from OS)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PortRange {
+    pub ranges: Vec<(u16, u16)>,
+}
+
+impl PortRange {
+    pub fn new(start: u16, end: u16) -> Option<Self> {
+        iff!(start != 0 && start <= end, Some(Self { ranges: vec![(start, end)] }), None)
+    }
+
+    pub fn parse(port_range: &str) -> Result<Self, IpAddressError> {
+        let invalid_port = || IpAddressError::new(IpAddressErrorKind::InvalidPort, port_range);
+        let mut ranges = vec![];
+        for port in port_range.split(',').map(str::trim) {
+            let (start, end) = match port.split_once('-') {
+                Some((start, end)) => (start.trim().parse::<u16>().map_err(|_| invalid_port())?,
+                                       end.trim().parse::<u16>().map_err(|_| invalid_port())?),
+                None => {
+                    let port = port.parse::<u16>().map_err(|_| invalid_port())?;
+                    (port, port)
+                }
+            };
+            if start == 0 || start > end {
+                return Err(invalid_port());
+            }
+            ranges.push((start, end));
+        }
+        Ok(Self { ranges })
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.ranges.iter().any(|(start, end)| *start <= port && port <= *end)
+    }
+
+    /// Port count, overlapped ports are counted repeatedly
+    pub fn len(&self) -> usize {
+        self.ranges.iter().map(|(start, end)| (end - start) as usize + 1).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=u16> + '_ {
+        self.ranges.iter().flat_map(|(start, end)| *start..=*end)
+    }
+}
+
+impl Display for PortRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.ranges.iter().map(|(start, end)| {
+            iff!(start == end, start.to_string(), format!("{}-{}", start, end))
+        }).collect::<Vec<_>>().join(","))
+    }
+}
+
+impl FromStr for PortRange {
+    type Err = IpAddressError;
+
+    fn from_str(port_range: &str) -> Result<Self, Self::Err> {
+        Self::parse(port_range)
+    }
+}
+
+/// Find a free TCP port assigned by OS, the port may be taken by others before it is used
+pub fn find_free_tcp_port(ip: IpAddr) -> XResult<u16> {
+    let listener = opt_result!(TcpListener::bind(SocketAddr::new(ip, 0)), "Bind TCP: {}:0 failed: {}", ip);
+    Ok(opt_result!(listener.local_addr(), "Get TCP local address failed: {}").port())
+}
+
+/// Find a free UDP port assigned by OS, the port may be taken by others before it is used
+pub fn find_free_udp_port(ip: IpAddr) -> XResult<u16> {
+    let socket = opt_result!(UdpSocket::bind(SocketAddr::new(ip, 0)), "Bind UDP: {}:0 failed: {}", ip);
+    Ok(opt_result!(socket.local_addr(), "Get UDP local address failed: {}").port())
+}
+
+/// Bind the first free TCP port in `port_range`, `reuse_addr` controls `SO_REUSEADDR` (unix only),
+/// only ports in use or not permitted are skipped, other errors are returned immediately
+pub fn bind_tcp_in_range(ip: IpAddr, port_range: &PortRange, reuse_addr: bool) -> XResult<TcpListener> {
+    // port 0 (random port from OS) in directly constructed range is skipped
+    for port in port_range.iter().filter(|port| *port != 0) {
+        match bind_tcp(&SocketAddr::new(ip, port), reuse_addr) {
+            Ok(listener) => return Ok(listener),
+            Err(e) if is_port_unavailable(&e) => {}
+            Err(e) => return simple_error!("Bind TCP: {}, failed: {}", SocketAddr::new(ip, port), e),
+        }
+    }
+    simple_error!("No free TCP port in: {}, address: {}", port_range, ip)
+}
+
+/// Bind the first free UDP port in `port_range`, `reuse_addr` controls `SO_REUSEADDR` (unix only),
+/// only ports in use or not permitted are skipped, other errors are returned immediately
+pub fn bind_udp_in_range(ip: IpAddr, port_range: &PortRange, reuse_addr: bool) -> XResult<UdpSocket> {
+    // port 0 (random port from OS) in directly constructed range is skipped
+    for port in port_range.iter().filter(|port| *port != 0) {
+        match bind_udp(&SocketAddr::new(ip, port), reuse_addr) {
+            Ok(socket) => return Ok(socket),
+            Err(e) if is_port_unavailable(&e) => {}
+            Err(e) => return simple_error!("Bind UDP: {}, failed: {}", SocketAddr::new(ip, port), e),
+        }
+    }
+    simple_error!("No free UDP port in: {}, address: {}", port_range, ip)
+}
+
+fn is_port_unavailable(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::AddrInUse | io::ErrorKind::PermissionDenied)
+}
+
+#[cfg(unix)]
+pub fn bind_tcp(addr: &SocketAddr, reuse_addr: bool) -> io::Result<TcpListener> {
+    use std::os::unix::io::FromRawFd;
+    let fd = bind_socket(addr, libc::SOCK_STREAM, reuse_addr)?;
+    Ok(unsafe { TcpListener::from_raw_fd(fd) })
+}
+
+#[cfg(unix)]
+pub fn bind_udp(addr: &SocketAddr, reuse_addr: bool) -> io::Result<UdpSocket> {
+    use std::os::unix::io::FromRawFd;
+    let fd = bind_socket(addr, libc::SOCK_DGRAM, reuse_addr)?;
+    Ok(unsafe { UdpSocket::from_raw_fd(fd) })
+}
+
+// `reuse_addr` is ignored on non-unix platforms
+#[cfg(not(unix))]
+pub fn bind_tcp(addr: &SocketAddr, _reuse_addr: bool) -> io::Result<TcpListener> {
+    TcpListener::bind(addr)
+}
+
+#[cfg(not(unix))]
+pub fn bind_udp(addr: &SocketAddr, _reuse_addr: bool) -> io::Result<UdpSocket> {
+    UdpSocket::bind(addr)
+}
+
+#[cfg(unix)]
+fn bind_socket(addr: &SocketAddr, socket_type: libc::c_int, reuse_addr: bool) -> io::Result<libc::c_int> {
+    use std::mem;
+    let family = iff!(addr.is_ipv4(), libc::AF_INET, libc::AF_INET6);
+    // set close-on-exec atomically where supported
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd"))]
+    let fd = unsafe { libc::socket(family, socket_type | libc::SOCK_CLOEXEC, 0) };
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+    let fd = unsafe { libc::socket(family, socket_type, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let close_with_error = || {
+        let e = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        Err(e)
+    };
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd", target_os = "netbsd", target_os = "openbsd")))]
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        return close_with_error();
+    }
+    let reuse_addr: libc::c_int = iff!(reuse_addr, 1, 0);
+    let r = unsafe {
+        libc::setsockopt(fd, libc::SOL_SOCKET, libc::SO_REUSEADDR, &reuse_addr as *const _ as *const libc::c_void,
+                         mem::size_of::<libc::c_int>() as libc::socklen_t)
+    };
+    if r < 0 {
+        return close_with_error();
+    }
+    let r = match addr {
+        SocketAddr::V4(addr) => {
+            let mut sockaddr: libc::sockaddr_in = unsafe { mem::zeroed() };
+            sockaddr.sin_family = libc::AF_INET as libc::sa_family_t;
+            sockaddr.sin_port = addr.port().to_be();
+            sockaddr.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(addr.ip().octets()) };
+            unsafe {
+                libc::bind(fd, &sockaddr as *const _ as *const libc::sockaddr, mem::size_of::<libc::sockaddr_in>() as libc::socklen_t)
+            }
+        }
+        SocketAddr::V6(addr) => {
+            let mut sockaddr: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            sockaddr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            sockaddr.sin6_port = addr.port().to_be();
+            sockaddr.sin6_addr.s6_addr = addr.ip().octets();
+            sockaddr.sin6_flowinfo = addr.flowinfo();
+            sockaddr.sin6_scope_id = addr.scope_id();
+            unsafe {
+                libc::bind(fd, &sockaddr as *const _ as *const libc::sockaddr, mem::size_of::<libc::sockaddr_in6>() as libc::socklen_t)
+            }
+        }
+    };
+    if r < 0 {
+        return close_with_error();
+    }
+    if socket_type == libc::SOCK_STREAM && unsafe { libc::listen(fd, 128) } < 0 {
+        return close_with_error();
+    }
+    Ok(fd)
+}
+
+#[test]
+fn test_port_range() {
+    let port_range = PortRange::parse("80, 443,8080-8082").unwrap();
+    assert_eq!("80,443,8080-8082", port_range.to_string());
+    assert_eq!(vec![80, 443, 8080, 8081, 8082], port_range.iter().collect::<Vec<_>>());
+    assert_eq!(5, port_range.len());
+    assert!(port_range.contains(8081));
+    assert!(!port_range.contains(8083));
+    assert_eq!(Some(PortRange { ranges: vec![(1, 65535)] }), "1-65535".parse().ok());
+    assert_eq!(65535, PortRange::new(1, 65535).unwrap().iter().count());
+    assert!(PortRange::new(2, 1).is_none());
+    assert!(PortRange::new(0, 1024).is_none());
+    for invalid in ["", "80,", "a", "90-80", "1-65536", "1-2-3", "0", "0-1024", "80,0"] {
+        assert_eq!(IpAddressErrorKind::InvalidPort, PortRange::parse(invalid).unwrap_err().kind, "{}", invalid);
+    }
+}
+
+#[test]
+fn test_bind_in_range() {
+    let localhost: IpAddr = "127.0.0.1".parse().unwrap();
+    assert_ne!(0, find_free_tcp_port(localhost).unwrap());
+    assert_ne!(0, find_free_udp_port(localhost).unwrap());
+
+    let taken_listener = bind_tcp(&SocketAddr::new(localhost, 0), false).unwrap();
+    let taken_port = taken_listener.local_addr().unwrap().port();
+    assert!(bind_tcp_in_range(localhost, &PortRange::new(taken_port, taken_port).unwrap(), true).is_err());
+    // range of 21 ports contains the taken port, and does not exceed 65535
+    let start_port = taken_port.min(u16::MAX - 20);
+    let port_range = PortRange::new(start_port, start_port + 20).unwrap();
+    let listener = bind_tcp_in_range(localhost, &port_range, false).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    assert!(port_range.contains(port) && port != taken_port);
+    // address not available is not retried
+    let not_local_ip: IpAddr = "240.0.0.1".parse().unwrap();
+    let error = bind_tcp_in_range(not_local_ip, &port_range, false).unwrap_err().to_string();
+    assert!(error.starts_with(&format!("Bind TCP: 240.0.0.1:{}, failed", start_port)), "{}", error);
+
+    let taken_socket = bind_udp(&SocketAddr::new(localhost, 0), false).unwrap();
+    let taken_port = taken_socket.local_addr().unwrap().port();
+    assert!(bind_udp_in_range(localhost, &PortRange::new(taken_port, taken_port).unwrap(), false).is_err());
+    assert!(bind_tcp_in_range(localhost, &PortRange { ranges: vec![(0, 0)] }, false).is_err());
+    assert!(bind_udp_in_range(localhost, &PortRange { ranges: vec![(0, 0)] }, false).is_err());
+}
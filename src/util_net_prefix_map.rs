@@ -0,0 +1,318 @@
+use crate::iff;
+use crate::util_net::{ipv4_to_u32, ipv6_mask, ipv6_to_u128, value_to_ip_address_mask, IpAddress, IpAddressMask};
+
+/// Map from CIDR prefix to value, backed by Patricia tries (one for IPv4, one for IPv6),
+/// supports longest prefix match
+pub struct IpPrefixMap<T> {
+    ipv4_root: Option<Box<Node<T>>>,
+    ipv6_root: Option<Box<Node<T>>>,
+    len: usize,
+}
+
+// IPv4 prefix is stored in the highest 32 bits of key
+struct Node<T> {
+    key: u128,
+    prefix_len: u8,
+    value: Option<T>,
+    children: [Option<Box<Node<T>>>; 2],
+}
+
+impl<T> Node<T> {
+    fn new(key: u128, prefix_len: u8, value: Option<T>) -> Self {
+        Self { key, prefix_len, value, children: [None, None] }
+    }
+
+    fn is_prefix_of(&self, key: u128, prefix_len: u8) -> bool {
+        self.prefix_len <= prefix_len && key & ipv6_mask(self.prefix_len) == self.key
+    }
+}
+
+impl<T> Default for IpPrefixMap<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> IpPrefixMap<T> {
+    pub fn new() -> Self {
+        Self { ipv4_root: None, ipv6_root: None, len: 0 }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Insert value for prefix, host bits of prefix are ignored, returns the old value
+    pub fn insert(&mut self, ip_address_mask: &IpAddressMask, value: T) -> Option<T> {
+        let (is_ipv4, key, prefix_len) = to_key(ip_address_mask);
+        let old_value = insert_node(self.get_root_mut(is_ipv4), key, prefix_len, value);
+        if old_value.is_none() {
+            self.len += 1;
+        }
+        old_value
+    }
+
+    pub fn remove(&mut self, ip_address_mask: &IpAddressMask) -> Option<T> {
+        let (is_ipv4, key, prefix_len) = to_key(ip_address_mask);
+        let value = remove_node(self.get_root_mut(is_ipv4), key, prefix_len);
+        if value.is_some() {
+            self.len -= 1;
+        }
+        value
+    }
+
+    /// Get value of the exact prefix
+    pub fn get(&self, ip_address_mask: &IpAddressMask) -> Option<&T> {
+        let (is_ipv4, key, prefix_len) = to_key(ip_address_mask);
+        let mut current = self.get_root(is_ipv4);
+        while let Some(node) = current {
+            if !node.is_prefix_of(key, prefix_len) {
+                return None;
+            }
+            if node.prefix_len == prefix_len {
+                return node.value.as_ref();
+            }
+            current = node.children[get_bit(key, node.prefix_len)].as_deref();
+        }
+        None
+    }
+
+    pub fn get_mut(&mut self, ip_address_mask: &IpAddressMask) -> Option<&mut T> {
+        let (is_ipv4, key, prefix_len) = to_key(ip_address_mask);
+        let mut current = self.get_root_mut(is_ipv4).as_deref_mut();
+        while let Some(node) = current {
+            if !node.is_prefix_of(key, prefix_len) {
+                return None;
+            }
+            if node.prefix_len == prefix_len {
+                return node.value.as_mut();
+            }
+            current = node.children[get_bit(key, node.prefix_len)].as_deref_mut();
+        }
+        None
+    }
+
+    pub fn contains_ip_address(&self, ip_address: &IpAddress) -> bool {
+        self.longest_match(ip_address).is_some()
+    }
+
+    /// Most specific prefix which contains the address, IPv4-mapped IPv6 address is looked up as IPv4
+    pub fn longest_match(&self, ip_address: &IpAddress) -> Option<(IpAddressMask, &T)> {
+        let (is_ipv4, key, prefix_len) = to_key(&IpAddressMask::from_ip_address(&ip_address.to_canonical()));
+        let mut last_match = None;
+        let mut current = self.get_root(is_ipv4);
+        while let Some(node) = current {
+            if !node.is_prefix_of(key, prefix_len) {
+                break;
+            }
+            if let Some(value) = &node.value {
+                last_match = Some((node.key, node.prefix_len, value));
+            }
+            if node.prefix_len == prefix_len {
+                break;
+            }
+            current = node.children[get_bit(key, node.prefix_len)].as_deref();
+        }
+        last_match.map(|(key, prefix_len, value)| (from_key(is_ipv4, key, prefix_len), value))
+    }
+
+    /// All prefixes which cover the given prefix (itself included), from the least to the most specific
+    pub fn covering_prefixes(&self, ip_address_mask: &IpAddressMask) -> Vec<(IpAddressMask, &T)> {
+        let (is_ipv4, key, prefix_len) = to_key(ip_address_mask);
+        let mut prefixes = vec![];
+        let mut current = self.get_root(is_ipv4);
+        while let Some(node) = current {
+            if !node.is_prefix_of(key, prefix_len) {
+                break;
+            }
+            if let Some(value) = &node.value {
+                prefixes.push((from_key(is_ipv4, node.key, node.prefix_len), value));
+            }
+            if node.prefix_len == prefix_len {
+                break;
+            }
+            current = node.children[get_bit(key, node.prefix_len)].as_deref();
+        }
+        prefixes
+    }
+
+    /// Iterate IPv4 prefixes then IPv6 prefixes, ordered by address then prefix length
+    pub fn iter(&self) -> IpPrefixMapIter<'_, T> {
+        let mut stack = vec![];
+        if let Some(ipv6_root) = &self.ipv6_root {
+            stack.push((false, ipv6_root.as_ref()));
+        }
+        if let Some(ipv4_root) = &self.ipv4_root {
+            stack.push((true, ipv4_root.as_ref()));
+        }
+        IpPrefixMapIter { stack }
+    }
+
+    fn get_root(&self, is_ipv4: bool) -> Option<&Node<T>> {
+        iff!(is_ipv4, self.ipv4_root.as_deref(), self.ipv6_root.as_deref())
+    }
+
+    fn get_root_mut(&mut self, is_ipv4: bool) -> &mut Option<Box<Node<T>>> {
+        iff!(is_ipv4, &mut self.ipv4_root, &mut self.ipv6_root)
+    }
+}
+
+pub struct IpPrefixMapIter<'a, T> {
+    stack: Vec<(bool, &'a Node<T>)>,
+}
+
+impl<'a, T> Iterator for IpPrefixMapIter<'a, T> {
+    type Item = (IpAddressMask, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some((is_ipv4, node)) = self.stack.pop() {
+            for child in node.children.iter().rev().flatten() {
+                self.stack.push((is_ipv4, child.as_ref()));
+            }
+            if let Some(value) = &node.value {
+                return Some((from_key(is_ipv4, node.key, node.prefix_len), value));
+            }
+        }
+        None
+    }
+}
+
+fn insert_node<T>(slot: &mut Option<Box<Node<T>>>, key: u128, prefix_len: u8, value: T) -> Option<T> {
+    let node = match slot {
+        None => {
+            *slot = Some(Box::new(Node::new(key, prefix_len, Some(value))));
+            return None;
+        }
+        Some(node) => node,
+    };
+    let common_len = get_common_prefix_len(node.key, key).min(node.prefix_len).min(prefix_len);
+    if common_len == node.prefix_len && common_len == prefix_len {
+        return node.value.replace(value);
+    }
+    if common_len == node.prefix_len {
+        return insert_node(&mut node.children[get_bit(key, common_len)], key, prefix_len, value);
+    }
+    let old_node = slot.take().unwrap();
+    let old_node_bit = get_bit(old_node.key, common_len);
+    let mut new_node = if common_len == prefix_len {
+        Node::new(key, prefix_len, Some(value))
+    } else {
+        let mut branch_node = Node::new(key & ipv6_mask(common_len), common_len, None);
+        branch_node.children[1 - old_node_bit] = Some(Box::new(Node::new(key, prefix_len, Some(value))));
+        branch_node
+    };
+    new_node.children[old_node_bit] = Some(old_node);
+    *slot = Some(Box::new(new_node));
+    None
+}
+
+fn remove_node<T>(slot: &mut Option<Box<Node<T>>>, key: u128, prefix_len: u8) -> Option<T> {
+    let node = slot.as_mut()?;
+    if !node.is_prefix_of(key, prefix_len) {
+        return None;
+    }
+    let value = if node.prefix_len == prefix_len {
+        node.value.take()
+    } else {
+        remove_node(&mut node.children[get_bit(key, node.prefix_len)], key, prefix_len)
+    };
+    // remove or merge node without value
+    if node.value.is_none() {
+        match (node.children[0].is_some(), node.children[1].is_some()) {
+            (false, false) => *slot = None,
+            (true, false) => *slot = node.children[0].take(),
+            (false, true) => *slot = node.children[1].take(),
+            (true, true) => {}
+        }
+    }
+    value
+}
+
+fn get_bit(key: u128, index: u8) -> usize {
+    ((key >> (127 - index as u32)) & 1) as usize
+}
+
+fn get_common_prefix_len(key1: u128, key2: u128) -> u8 {
+    (key1 ^ key2).leading_zeros() as u8
+}
+
+fn from_key(is_ipv4: bool, key: u128, prefix_len: u8) -> IpAddressMask {
+    value_to_ip_address_mask(is_ipv4, iff!(is_ipv4, key >> 96, key), prefix_len)
+}
+
+fn to_key(ip_address_mask: &IpAddressMask) -> (bool, u128, u8) {
+    match ip_address_mask {
+        IpAddressMask::Ipv4(ipv4, mask) => (true, ((ipv4_to_u32(ipv4) as u128) << 96) & ipv6_mask(*mask), *mask),
+        IpAddressMask::Ipv6(ipv6, mask) => (false, ipv6_to_u128(ipv6) & ipv6_mask(*mask), *mask),
+    }
+}
+
+#[test]
+fn test_ip_prefix_map() {
+    let mask = |s: &str| IpAddressMask::parse(s).unwrap();
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    let format_match = |m: Option<(IpAddressMask, &&str)>| m.map(|(m, v)| format!("{} {}", m, v)).unwrap_or_default();
+    let mut map = IpPrefixMap::new();
+    assert_eq!(None, map.insert(&mask("10.0.0.0/8"), "tenant-a"));
+    assert_eq!(None, map.insert(&mask("10.1.0.0/16"), "tenant-b"));
+    assert_eq!(None, map.insert(&mask("10.1.2.0/24"), "tenant-c"));
+    assert_eq!(None, map.insert(&mask("192.168.0.0/16"), "lan"));
+    assert_eq!(None, map.insert(&mask("0.0.0.0/0"), "default"));
+    assert_eq!(None, map.insert(&mask("2001:db8::/32"), "v6"));
+    assert_eq!(Some("lan"), map.insert(&mask("192.168.1.1/16"), "lan2"));
+    assert_eq!(6, map.len());
+
+    assert_eq!(Some(&"tenant-c"), map.get(&mask("10.1.2.0/24")));
+    assert_eq!(None, map.get(&mask("10.1.2.0/23")));
+    assert_eq!("10.1.2.0/24 tenant-c", format_match(map.longest_match(&ip("10.1.2.3"))));
+    assert_eq!("10.1.0.0/16 tenant-b", format_match(map.longest_match(&ip("10.1.3.3"))));
+    assert_eq!("10.0.0.0/8 tenant-a", format_match(map.longest_match(&ip("10.2.3.3"))));
+    assert_eq!("10.0.0.0/8 tenant-a", format_match(map.longest_match(&ip("::ffff:10.2.3.3"))));
+    assert_eq!("192.168.0.0/16 lan2", format_match(map.longest_match(&ip("192.168.3.3"))));
+    assert_eq!("0.0.0.0/0 default", format_match(map.longest_match(&ip("8.8.8.8"))));
+    assert_eq!("2001:db8::/32 v6", format_match(map.longest_match(&ip("2001:db8::1"))));
+    assert!(map.longest_match(&ip("2001:db9::1")).is_none());
+
+    assert_eq!(vec!["0.0.0.0/0", "10.0.0.0/8", "10.1.0.0/16", "10.1.2.0/24"],
+               map.covering_prefixes(&mask("10.1.2.3")).iter().map(|(m, _)| m.to_string()).collect::<Vec<_>>());
+    assert_eq!(vec!["0.0.0.0/0 default", "10.0.0.0/8 tenant-a", "10.1.0.0/16 tenant-b", "10.1.2.0/24 tenant-c", "192.168.0.0/16 lan2", "2001:db8::/32 v6"],
+               map.iter().map(|(m, v)| format!("{} {}", m, v)).collect::<Vec<_>>());
+
+    *map.get_mut(&mask("10.1.0.0/16")).unwrap() = "tenant-d";
+    assert_eq!(Some("tenant-d"), map.remove(&mask("10.1.0.0/16")));
+    assert_eq!(None, map.remove(&mask("10.1.0.0/16")));
+    assert_eq!(None, map.remove(&mask("10.1.0.0/17")));
+    assert_eq!("10.0.0.0/8 tenant-a", format_match(map.longest_match(&ip("10.1.3.3"))));
+    assert_eq!("10.1.2.0/24 tenant-c", format_match(map.longest_match(&ip("10.1.2.3"))));
+    assert_eq!(Some("default"), map.remove(&mask("0.0.0.0/0")));
+    assert!(map.longest_match(&ip("8.8.8.8")).is_none());
+    assert_eq!(4, map.len());
+    assert_eq!(4, map.iter().count());
+}
+
+#[test]
+fn test_ip_prefix_map_split() {
+    let mut map = IpPrefixMap::new();
+    for (i, m) in ["1.2.3.4/32", "1.2.3.5/32", "1.2.3.0/30", "1.2.0.0/16", "1.2.3.4/31", "128.0.0.0/1"].iter().enumerate() {
+        map.insert(&IpAddressMask::parse(m).unwrap(), i);
+    }
+    assert_eq!(vec!["1.2.0.0/16", "1.2.3.0/30", "1.2.3.4/31", "1.2.3.4/32", "1.2.3.5/32", "128.0.0.0/1"],
+               map.iter().map(|(m, _)| m.to_string()).collect::<Vec<_>>());
+    assert_eq!(Some(&2), map.longest_match(&IpAddress::Ipv4([1, 2, 3, 1])).map(|(_, v)| v));
+    assert_eq!(Some(&5), map.longest_match(&IpAddress::Ipv4([200, 2, 3, 1])).map(|(_, v)| v));
+    assert_eq!(Some(&0), map.longest_match(&IpAddress::Ipv4([1, 2, 3, 4])).map(|(_, v)| v));
+    for m in ["1.2.3.4/32", "1.2.3.5/32", "1.2.3.0/30", "1.2.0.0/16", "1.2.3.4/31", "128.0.0.0/1"].iter() {
+        assert!(map.remove(&IpAddressMask::parse(m).unwrap()).is_some());
+    }
+    assert!(map.is_empty());
+    assert!(map.ipv4_root.is_none());
+
+    map.insert(&IpAddressMask::parse("2001:db8::1/128").unwrap(), 0);
+    map.insert(&IpAddressMask::parse("2001:db8::/64").unwrap(), 1);
+    assert_eq!(Some(&0), map.longest_match(&"2001:db8::1".parse().unwrap()).map(|(_, v)| v));
+    assert_eq!(Some(&1), map.longest_match(&"2001:db8::2".parse().unwrap()).map(|(_, v)| v));
+}
@@ -0,0 +1,203 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::util_net::{ipv4_to_u32, ipv6_mask, ipv6_to_u128, IpAddress, IpAndIpMaskMatcher};
+use crate::{simple_error, XResult};
+
+pub const DEFAULT_MAX_ENTRIES: usize = 100_000;
+
+#[derive(Debug, Clone)]
+pub struct RateLimiterConfig {
+    // tokens refilled per second
+    pub rate: f64,
+    // bucket capacity
+    pub burst: u32,
+    // addresses in the same prefix share one bucket, 32 and 128 means per address
+    pub ipv4_mask_len: u8,
+    pub ipv6_mask_len: u8,
+    // least recently used bucket is evicted when exceeded
+    pub max_entries: usize,
+}
+
+impl RateLimiterConfig {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self { rate, burst, ipv4_mask_len: 32, ipv6_mask_len: 128, max_entries: DEFAULT_MAX_ENTRIES }
+    }
+
+    /// e.g. limit by `/24` for IPv4 and `/64` for IPv6
+    pub fn with_mask_len(mut self, ipv4_mask_len: u8, ipv6_mask_len: u8) -> Self {
+        self.ipv4_mask_len = ipv4_mask_len.min(32);
+        self.ipv6_mask_len = ipv6_mask_len.min(128);
+        self
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries.max(1);
+        self
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+    // key in `lru`
+    last_used: u64,
+}
+
+// (is IPv4, masked address)
+type BucketKey = (bool, u128);
+
+#[derive(Default)]
+struct Buckets {
+    buckets: HashMap<BucketKey, Bucket>,
+    // last used sequence -> key, the first one is the least recently used
+    lru: BTreeMap<u64, BucketKey>,
+    sequence: u64,
+}
+
+/// Token bucket rate limiter keyed by IP address or its enclosing prefix
+pub struct IpRateLimiter {
+    config: RateLimiterConfig,
+    allowlist: Option<IpAndIpMaskMatcher>,
+    buckets: Mutex<Buckets>,
+}
+
+impl IpRateLimiter {
+    /// Fails when rate is not a positive finite number or burst is 0
+    pub fn new(config: RateLimiterConfig) -> XResult<Self> {
+        if !(config.rate.is_finite() && config.rate > 0.0) {
+            return simple_error!("Invalid rate limiter rate: {}", config.rate);
+        }
+        if config.burst == 0 {
+            return simple_error!("Invalid rate limiter burst: 0");
+        }
+        Ok(Self { config, allowlist: None, buckets: Mutex::new(Buckets::default()) })
+    }
+
+    /// Addresses in allowlist are never limited
+    pub fn with_allowlist(mut self, allowlist: IpAndIpMaskMatcher) -> Self {
+        self.allowlist = Some(allowlist);
+        self
+    }
+
+    /// Consumes one token of the peer address when allowed, not a pure check like `is_matches` elsewhere
+    pub fn check_and_consume(&self, socket_addr: &SocketAddr) -> bool {
+        self.try_acquire(&socket_addr.ip().into(), 1)
+    }
+
+    pub fn try_acquire(&self, ip_address: &IpAddress, tokens: u32) -> bool {
+        self.try_acquire_at(ip_address, tokens, Instant::now())
+    }
+
+    /// Same as `try_acquire` with given current time
+    pub fn try_acquire_at(&self, ip_address: &IpAddress, tokens: u32, now: Instant) -> bool {
+        let ip_address = ip_address.to_canonical();
+        if self.allowlist.as_ref().map(|a| a.contains_ip_address(&ip_address)).unwrap_or(false) {
+            return true;
+        }
+        let key = self.get_bucket_key(&ip_address);
+        let mut buckets = self.buckets.lock().unwrap();
+        let buckets = &mut *buckets;
+        buckets.sequence += 1;
+        let sequence = buckets.sequence;
+        let bucket = match buckets.buckets.get_mut(&key) {
+            Some(bucket) => {
+                buckets.lru.remove(&bucket.last_used);
+                let elapsed = now.saturating_duration_since(bucket.last_refill).as_secs_f64();
+                bucket.tokens = (bucket.tokens + elapsed * self.config.rate).min(self.config.burst as f64);
+                bucket.last_refill = bucket.last_refill.max(now);
+                bucket
+            }
+            None => {
+                if buckets.buckets.len() >= self.config.max_entries {
+                    if let Some((_, evicted_key)) = buckets.lru.pop_first() {
+                        buckets.buckets.remove(&evicted_key);
+                    }
+                }
+                buckets.buckets.entry(key).or_insert(Bucket { tokens: self.config.burst as f64, last_refill: now, last_used: 0 })
+            }
+        };
+        bucket.last_used = sequence;
+        buckets.lru.insert(sequence, key);
+        if bucket.tokens >= tokens as f64 {
+            bucket.tokens -= tokens as f64;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Tracked bucket count
+    pub fn len(&self) -> usize {
+        self.buckets.lock().unwrap().buckets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get_bucket_key(&self, ip_address: &IpAddress) -> BucketKey {
+        match ip_address {
+            IpAddress::Ipv4(ipv4) => (true, (ipv4_to_u32(ipv4) as u128) & (ipv6_mask(self.config.ipv4_mask_len) >> 96)),
+            IpAddress::Ipv6(ipv6) => (false, ipv6_to_u128(ipv6) & ipv6_mask(self.config.ipv6_mask_len)),
+        }
+    }
+}
+
+#[test]
+fn test_ip_rate_limiter() {
+    use std::time::Duration;
+    let limiter = IpRateLimiter::new(RateLimiterConfig::new(2.0, 3)).unwrap();
+    let ip = IpAddress::Ipv4([1, 2, 3, 4]);
+    let now = Instant::now();
+    assert!(limiter.try_acquire_at(&ip, 1, now));
+    assert!(limiter.try_acquire_at(&ip, 2, now));
+    assert!(!limiter.try_acquire_at(&ip, 1, now));
+    assert!(limiter.try_acquire(&IpAddress::Ipv4([1, 2, 3, 5]), 1));
+    // refill 2 tokens per second
+    assert!(limiter.try_acquire_at(&ip, 1, now + Duration::from_millis(500)));
+    assert!(!limiter.try_acquire_at(&ip, 1, now + Duration::from_millis(500)));
+    assert!(limiter.try_acquire_at(&ip, 3, now + Duration::from_secs(10)));
+    // IPv4-mapped address shares the same bucket
+    assert!(!limiter.try_acquire_at(&IpAddress::parse("::ffff:1.2.3.4").unwrap(), 1, now + Duration::from_secs(10)));
+    assert!(!limiter.try_acquire_at(&ip, 4, now + Duration::from_secs(100)));
+    for (rate, burst) in [(f64::NAN, 1), (f64::INFINITY, 1), (-1.0, 1), (0.0, 1), (1.0, 0)] {
+        assert!(IpRateLimiter::new(RateLimiterConfig::new(rate, burst)).is_err(), "{} {}", rate, burst);
+    }
+}
+
+#[test]
+fn test_ip_rate_limiter_subnet_and_allowlist() {
+    let mut allowlist = IpAndIpMaskMatcher::new();
+    allowlist.add_ip_address_mask(&IpAddress::Ipv4([10, 0, 0, 0]), 8);
+    let limiter = IpRateLimiter::new(RateLimiterConfig::new(1.0, 2).with_mask_len(24, 64)).unwrap()
+        .with_allowlist(allowlist);
+    let now = Instant::now();
+    assert!(limiter.try_acquire_at(&IpAddress::Ipv4([192, 168, 1, 1]), 1, now));
+    assert!(limiter.try_acquire_at(&IpAddress::Ipv4([192, 168, 1, 2]), 1, now));
+    assert!(!limiter.try_acquire_at(&IpAddress::Ipv4([192, 168, 1, 3]), 1, now));
+    assert!(limiter.try_acquire_at(&IpAddress::Ipv4([192, 168, 2, 1]), 1, now));
+    assert!(limiter.try_acquire_at(&IpAddress::parse("2001:db8::1").unwrap(), 2, now));
+    assert!(!limiter.try_acquire_at(&IpAddress::parse("2001:db8::ffff").unwrap(), 1, now));
+    for _ in 0..10 {
+        assert!(limiter.check_and_consume(&"10.1.2.3:80".parse().unwrap()));
+    }
+    assert_eq!(3, limiter.len());
+}
+
+#[test]
+fn test_ip_rate_limiter_lru_eviction() {
+    let limiter = IpRateLimiter::new(RateLimiterConfig::new(1.0, 1).with_max_entries(2)).unwrap();
+    let now = Instant::now();
+    let (a, b, c) = (IpAddress::Ipv4([1, 1, 1, 1]), IpAddress::Ipv4([2, 2, 2, 2]), IpAddress::Ipv4([3, 3, 3, 3]));
+    assert!(limiter.try_acquire_at(&a, 1, now));
+    assert!(limiter.try_acquire_at(&b, 1, now));
+    // `a` is used recently, `b` is evicted
+    assert!(!limiter.try_acquire_at(&a, 1, now));
+    assert!(limiter.try_acquire_at(&c, 1, now));
+    assert_eq!(2, limiter.len());
+    assert!(!limiter.try_acquire_at(&a, 1, now));
+    assert!(limiter.try_acquire_at(&b, 1, now));
+}
@@ -0,0 +1,421 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::util_io::{self, PrintStatusContext};
+use crate::util_net::{IpAddressAndPort, IpAddressMaskGroup};
+use crate::util_net_host::HostAndPort;
+use crate::{opt_result, simple_error, util_msg, util_size, XResult};
+
+pub const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct TcpRelayConfig {
+    pub listen: IpAddressAndPort,
+    pub upstream: HostAndPort,
+    // empty means allow all peers
+    pub allowed_peers: IpAddressMaskGroup,
+    pub max_connections: Option<usize>,
+    pub idle_timeout: Option<Duration>,
+    pub connect_timeout: Duration,
+}
+
+impl TcpRelayConfig {
+    pub fn new(listen: IpAddressAndPort, upstream: HostAndPort) -> Self {
+        Self {
+            listen,
+            upstream,
+            allowed_peers: IpAddressMaskGroup { ip_address_mask_group: vec![] },
+            max_connections: None,
+            idle_timeout: None,
+            connect_timeout: DEFAULT_CONNECT_TIMEOUT,
+        }
+    }
+
+    pub fn with_allowed_peers(mut self, allowed_peers: IpAddressMaskGroup) -> Self {
+        self.allowed_peers = allowed_peers;
+        self
+    }
+
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = Some(max_connections);
+        self
+    }
+
+    /// Connection is closed when no bytes transferred in both directions for `idle_timeout`
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self.connect_timeout = connect_timeout;
+        self
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionStats {
+    pub peer_addr: SocketAddr,
+    pub upstream_addr: SocketAddr,
+    // peer -> upstream
+    pub bytes_sent: u64,
+    // upstream -> peer
+    pub bytes_received: u64,
+    pub duration: Duration,
+}
+
+type ConnectionClosedCallback = dyn Fn(&ConnectionStats) + Send + Sync;
+
+pub struct TcpRelay {
+    config: Arc<TcpRelayConfig>,
+    listener: TcpListener,
+    active_connections: Arc<AtomicUsize>,
+    is_stopped: AtomicBool,
+    on_connection_closed: Option<Arc<ConnectionClosedCallback>>,
+}
+
+impl TcpRelay {
+    pub fn bind(config: TcpRelayConfig) -> XResult<Self> {
+        let listener = opt_result!(TcpListener::bind(SocketAddr::from(config.listen.clone())), "Listen: {}, failed: {}", config.listen);
+        Ok(Self {
+            config: Arc::new(config),
+            listener,
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            is_stopped: AtomicBool::new(false),
+            on_connection_closed: None,
+        })
+    }
+
+    pub fn with_on_connection_closed<F>(mut self, callback: F) -> Self where F: Fn(&ConnectionStats) + Send + Sync + 'static {
+        self.on_connection_closed = Some(Arc::new(callback));
+        self
+    }
+
+    pub fn local_addr(&self) -> XResult<SocketAddr> {
+        Ok(opt_result!(self.listener.local_addr(), "Get local address failed: {}"))
+    }
+
+    pub fn get_active_connections(&self) -> usize {
+        self.active_connections.load(Ordering::SeqCst)
+    }
+
+    /// Accept and relay connections until `stop()`, each connection is relayed in its own threads
+    pub fn run(&self) -> XResult<()> {
+        util_msg::print_info(&format!("TCP relay started: {} -> {}", self.local_addr()?, self.config.upstream));
+        for stream in self.listener.incoming() {
+            if self.is_stopped.load(Ordering::SeqCst) {
+                break;
+            }
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(e) => {
+                    util_msg::print_warn(&format!("Accept connection failed: {}", e));
+                    continue;
+                }
+            };
+            self.accept_connection(stream);
+        }
+        util_msg::print_info(&format!("TCP relay stopped: {}", self.local_addr()?));
+        Ok(())
+    }
+
+    /// Stop accepting new connections, established connections are not closed
+    pub fn stop(&self) {
+        self.is_stopped.store(true, Ordering::SeqCst);
+        // wake up the blocking accept
+        if let Ok(mut local_addr) = self.listener.local_addr() {
+            if local_addr.ip().is_unspecified() {
+                local_addr.set_ip(get_loopback_ip(&local_addr));
+            }
+            let _ = TcpStream::connect_timeout(&local_addr, Duration::from_secs(1));
+        }
+    }
+
+    fn accept_connection(&self, stream: TcpStream) {
+        let peer_addr = match stream.peer_addr() {
+            Ok(peer_addr) => peer_addr,
+            Err(e) => {
+                util_msg::print_warn(&format!("Get peer address failed: {}", e));
+                return;
+            }
+        };
+        if !self.config.allowed_peers.is_empty_or_matches(&peer_addr) {
+            util_msg::print_warn(&format!("Connection denied: {}", peer_addr));
+            let _ = stream.shutdown(Shutdown::Both);
+            return;
+        }
+        let active_connections = self.active_connections.fetch_add(1, Ordering::SeqCst) + 1;
+        if let Some(max_connections) = self.config.max_connections {
+            if active_connections > max_connections {
+                self.active_connections.fetch_sub(1, Ordering::SeqCst);
+                util_msg::print_warn(&format!("Connection limit {} reached, reject: {}", max_connections, peer_addr));
+                let _ = stream.shutdown(Shutdown::Both);
+                return;
+            }
+        }
+        let config = self.config.clone();
+        let active_connections = self.active_connections.clone();
+        let on_connection_closed = self.on_connection_closed.clone();
+        thread::spawn(move || {
+            match relay_connection(stream, peer_addr, &config) {
+                Ok(stats) => {
+                    util_msg::print_info(&format!("Connection closed: {} -> {}, sent: {}, received: {}, duration: {}ms",
+                                                  stats.peer_addr, stats.upstream_addr,
+                                                  util_size::get_display_size(stats.bytes_sent as i64),
+                                                  util_size::get_display_size(stats.bytes_received as i64),
+                                                  stats.duration.as_millis()));
+                    if let Some(on_connection_closed) = on_connection_closed {
+                        on_connection_closed(&stats);
+                    }
+                }
+                Err(e) => util_msg::print_warn(&format!("Relay connection: {}, failed: {}", peer_addr, e)),
+            }
+            active_connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+fn get_loopback_ip(addr: &SocketAddr) -> IpAddr {
+    match addr {
+        SocketAddr::V4(_) => Ipv4Addr::LOCALHOST.into(),
+        SocketAddr::V6(_) => Ipv6Addr::LOCALHOST.into(),
+    }
+}
+
+fn relay_connection(peer: TcpStream, peer_addr: SocketAddr, config: &TcpRelayConfig) -> XResult<ConnectionStats> {
+    let start = Instant::now();
+    let upstream = connect_upstream(&config.upstream, config.connect_timeout)?;
+    let upstream_addr = opt_result!(upstream.peer_addr(), "Get upstream address failed: {}");
+    util_msg::print_info(&format!("Connection accepted: {} -> {}", peer_addr, upstream_addr));
+    // a peer which stops reading blocks writes, so writes need timeout too
+    for stream in [&peer, &upstream] {
+        opt_result!(stream.set_read_timeout(config.idle_timeout), "Set read timeout failed: {}");
+        opt_result!(stream.set_write_timeout(config.idle_timeout), "Set write timeout failed: {}");
+    }
+    // milliseconds since `start` of the last transfer in any direction
+    let last_active_millis = Arc::new(AtomicU64::new(0));
+    let bytes_sent = Arc::new(AtomicU64::new(0));
+    let bytes_received = Arc::new(AtomicU64::new(0));
+
+    let peer_to_upstream = {
+        let (mut reader, mut writer) = (peer.try_clone()?, upstream.try_clone()?);
+        let (bytes_sent, last_active_millis, idle_timeout) = (bytes_sent.clone(), last_active_millis.clone(), config.idle_timeout);
+        thread::spawn(move || pipe(&mut reader, &mut writer, &bytes_sent, &last_active_millis, start, idle_timeout))
+    };
+    let (mut reader, mut writer) = (upstream, peer);
+    pipe(&mut reader, &mut writer, &bytes_received, &last_active_millis, start, config.idle_timeout);
+    let _ = peer_to_upstream.join();
+
+    Ok(ConnectionStats {
+        peer_addr,
+        upstream_addr,
+        bytes_sent: bytes_sent.load(Ordering::SeqCst),
+        bytes_received: bytes_received.load(Ordering::SeqCst),
+        duration: start.elapsed(),
+    })
+}
+
+fn connect_upstream(upstream: &HostAndPort, connect_timeout: Duration) -> XResult<TcpStream> {
+    let mut last_error = None;
+    for upstream_addr in upstream.resolve()? {
+        match TcpStream::connect_timeout(&upstream_addr, connect_timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+    match last_error {
+        Some(e) => simple_error!("Connect upstream: {}, failed: {}", upstream, e),
+        None => simple_error!("Connect upstream: {}, failed: no address resolved", upstream),
+    }
+}
+
+// copy until EOF, error, or idle timeout, both reads and writes count as activity
+fn pipe(reader: &mut TcpStream, writer: &mut TcpStream, bytes: &AtomicU64, last_active_millis: &AtomicU64,
+        start: Instant, idle_timeout: Option<Duration>) {
+    let activity = Activity { last_active_millis, start, idle_timeout };
+    let result = util_io::copy_io_callback(&mut IdleTimeoutStream { stream: &mut *reader, activity: &activity },
+                                           &mut IdleTimeoutStream { stream: &mut *writer, activity: &activity },
+                                           -1, &mut PrintStatusContext::default(), &mut |_total, _written, len, _print_status_context| {
+            bytes.fetch_add(len as u64, Ordering::SeqCst);
+        });
+    match result {
+        // EOF, half close so the other direction can finish
+        Ok(_) => {
+            let _ = writer.shutdown(Shutdown::Write);
+        }
+        Err(_) => {
+            let _ = reader.shutdown(Shutdown::Both);
+            let _ = writer.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+// last activity of both directions
+struct Activity<'a> {
+    last_active_millis: &'a AtomicU64,
+    start: Instant,
+    idle_timeout: Option<Duration>,
+}
+
+impl Activity<'_> {
+    fn mark_active(&self) {
+        self.last_active_millis.store(self.start.elapsed().as_millis() as u64, Ordering::SeqCst);
+    }
+
+    fn is_idle(&self) -> bool {
+        let idle_millis = (self.start.elapsed().as_millis() as u64).saturating_sub(self.last_active_millis.load(Ordering::SeqCst));
+        idle_millis >= self.idle_timeout.map(|t| t.as_millis() as u64).unwrap_or(u64::MAX)
+    }
+}
+
+// stream with read and write timeout, timeout in one direction is retried while the other direction is active
+struct IdleTimeoutStream<'a> {
+    stream: &'a mut TcpStream,
+    activity: &'a Activity<'a>,
+}
+
+impl IdleTimeoutStream<'_> {
+    fn retry_until_idle<F: FnMut(&mut TcpStream) -> io::Result<usize>>(&mut self, mut f: F) -> io::Result<usize> {
+        loop {
+            match f(self.stream) {
+                Ok(len) => {
+                    self.activity.mark_active();
+                    return Ok(len);
+                }
+                Err(e) if is_timeout(&e) && !self.activity.is_idle() => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl Read for IdleTimeoutStream<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.retry_until_idle(|stream| stream.read(buf))
+    }
+}
+
+impl Write for IdleTimeoutStream<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.retry_until_idle(|stream| stream.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+#[test]
+fn test_tcp_relay() {
+    use std::io::{Read, Write};
+    use std::sync::mpsc;
+
+    let echo_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let echo_addr = echo_listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for stream in echo_listener.incoming().flatten() {
+            thread::spawn(move || {
+                let mut reader = stream.try_clone().unwrap();
+                let mut writer = stream;
+                let _ = io::copy(&mut reader, &mut writer);
+                let _ = writer.shutdown(Shutdown::Write);
+            });
+        }
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let config = TcpRelayConfig::new(IpAddressAndPort::parse("127.0.0.1:0").unwrap(), echo_addr.into())
+        .with_idle_timeout(Duration::from_millis(200));
+    let relay = Arc::new(TcpRelay::bind(config).unwrap()
+        .with_on_connection_closed(move |stats| sender.send(stats.clone()).unwrap()));
+    let relay_addr = relay.local_addr().unwrap();
+    let relay_thread = {
+        let relay = relay.clone();
+        thread::spawn(move || relay.run().map_err(|e| e.to_string()))
+    };
+
+    let mut client = TcpStream::connect(relay_addr).unwrap();
+    client.write_all(b"hello relay").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let mut response = vec![];
+    client.read_to_end(&mut response).unwrap();
+    assert_eq!(b"hello relay".to_vec(), response);
+    let stats = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!((11, 11), (stats.bytes_sent, stats.bytes_received));
+    assert_eq!(echo_addr, stats.upstream_addr);
+
+    // idle connection is closed
+    let mut client = TcpStream::connect(relay_addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut response = vec![];
+    let _ = client.read_to_end(&mut response);
+    let stats = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(stats.duration >= Duration::from_millis(200) && stats.duration < Duration::from_secs(5));
+    assert_eq!(0, stats.bytes_sent);
+
+    relay.stop();
+    relay_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_tcp_relay_peer_stops_reading() {
+    use std::io::Write;
+    use std::sync::mpsc;
+
+    // upstream keeps writing until the connection is closed
+    let flood_listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let flood_addr = flood_listener.local_addr().unwrap();
+    thread::spawn(move || {
+        for mut stream in flood_listener.incoming().flatten() {
+            thread::spawn(move || while stream.write_all(&[0_u8; 64 * 1024]).is_ok() {});
+        }
+    });
+
+    let (sender, receiver) = mpsc::channel();
+    let config = TcpRelayConfig::new(IpAddressAndPort::parse("127.0.0.1:0").unwrap(), flood_addr.into())
+        .with_idle_timeout(Duration::from_millis(200));
+    let relay = Arc::new(TcpRelay::bind(config).unwrap()
+        .with_on_connection_closed(move |stats| sender.send(stats.clone()).unwrap()));
+    let relay_addr = relay.local_addr().unwrap();
+    let relay_thread = {
+        let relay = relay.clone();
+        thread::spawn(move || relay.run().map_err(|e| e.to_string()))
+    };
+
+    // the client half closes and never reads, writes to it block once socket buffers are full
+    let client = TcpStream::connect(relay_addr).unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+    let stats = receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+    assert!(stats.bytes_received > 0);
+
+    relay.stop();
+    relay_thread.join().unwrap().unwrap();
+}
+
+#[test]
+fn test_tcp_relay_denied() {
+    use std::io::Read;
+
+    let config = TcpRelayConfig::new(IpAddressAndPort::parse("127.0.0.1:0").unwrap(), HostAndPort::parse("127.0.0.1:1", None).unwrap())
+        .with_allowed_peers(IpAddressMaskGroup::parse(&["10.0.0.0/8".to_string()]));
+    let relay = Arc::new(TcpRelay::bind(config).unwrap());
+    let relay_addr = relay.local_addr().unwrap();
+    let relay_thread = {
+        let relay = relay.clone();
+        thread::spawn(move || relay.run().map_err(|e| e.to_string()))
+    };
+    let mut client = TcpStream::connect(relay_addr).unwrap();
+    client.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    let mut buf = [0_u8; 1];
+    assert!(matches!(client.read(&mut buf), Ok(0) | Err(_)));
+    relay.stop();
+    relay_thread.join().unwrap().unwrap();
+}
@@ -0,0 +1,352 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+use crate::util_net::IpAddress;
+use crate::util_net_host::is_valid_hostname;
+use crate::util_net_loader::IpListEntryError;
+use crate::{opt_result, simple_error, XResult};
+
+pub const DEFAULT_HOSTS_FILE: &str = "/etc/hosts";
+pub const DEFAULT_RESOLV_CONF_FILE: &str = "/etc/resolv.conf";
+// resolver uses at most 3 nameservers, see `MAXNS` in resolv.h
+pub const MAX_NAMESERVERS: usize = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostsEntry {
+    pub ip_address: IpAddress,
+    // IPv6 zone index, e.g. `eth0` in `fe80::1%eth0`
+    pub zone: Option<String>,
+    // canonical name first, then aliases
+    pub hostnames: Vec<String>,
+}
+
+/// Hosts file like `/etc/hosts`, `#` starts a comment
+#[derive(Debug, Clone, Default)]
+pub struct HostsFile {
+    pub entries: Vec<HostsEntry>,
+    // invalid lines are skipped and recorded here
+    pub errors: Vec<IpListEntryError>,
+}
+
+impl HostsFile {
+    pub fn load() -> XResult<Self> {
+        Self::load_file(DEFAULT_HOSTS_FILE)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        let path = path.as_ref();
+        let file = opt_result!(File::open(path), "Open hosts file: {}, failed: {}", path.display());
+        Self::load_reader(BufReader::new(file), &path.display().to_string())
+    }
+
+    pub fn load_reader<R: BufRead>(reader: R, name: &str) -> XResult<Self> {
+        let mut hosts_file = Self::default();
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = opt_result!(line, "Read hosts file: {}, failed: {}", name);
+            let entry = line.split('#').next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let mut add_error = |message: &str| hosts_file.errors.push(IpListEntryError {
+                file: name.to_string(),
+                line: line_index + 1,
+                entry: entry.to_string(),
+                message: message.to_string(),
+            });
+            let mut fields = entry.split_whitespace();
+            let (ip_address, zone) = match fields.next().and_then(parse_ip_address) {
+                Some(ip_address_and_zone) => ip_address_and_zone,
+                None => {
+                    add_error("Invalid IP address");
+                    continue;
+                }
+            };
+            let hostnames = fields.map(ToString::to_string).collect::<Vec<_>>();
+            if hostnames.is_empty() {
+                add_error("Missing hostname");
+                continue;
+            }
+            if let Some(hostname) = hostnames.iter().find(|h| !is_valid_hostname(h)) {
+                add_error(&format!("Invalid hostname: {}", hostname));
+                continue;
+            }
+            hosts_file.entries.push(HostsEntry { ip_address, zone, hostnames });
+        }
+        Ok(hosts_file)
+    }
+
+    pub fn ensure_no_errors(self) -> XResult<Self> {
+        if self.errors.is_empty() {
+            return Ok(self);
+        }
+        simple_error!("Load hosts file with {} invalid lines: {}", self.errors.len(),
+            self.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))
+    }
+
+    /// Addresses and zone indexes of `hostname` in file order, case insensitive and trailing dot is ignored
+    pub fn lookup(&self, hostname: &str) -> Vec<(IpAddress, Option<String>)> {
+        let hostname = hostname.strip_suffix('.').unwrap_or(hostname);
+        let mut ip_addresses = vec![];
+        for entry in &self.entries {
+            let is_matches = entry.hostnames.iter()
+                .any(|h| h.strip_suffix('.').unwrap_or(h).eq_ignore_ascii_case(hostname));
+            let ip_address_and_zone = (entry.ip_address.clone(), entry.zone.clone());
+            if is_matches && !ip_addresses.contains(&ip_address_and_zone) {
+                ip_addresses.push(ip_address_and_zone);
+            }
+        }
+        ip_addresses
+    }
+
+    /// Hostnames of `ip_address` in file order, IPv4-mapped IPv6 address is matched as IPv4 address
+    pub fn reverse_lookup(&self, ip_address: &IpAddress) -> Vec<String> {
+        let ip_address = ip_address.to_canonical();
+        let mut hostnames: Vec<String> = vec![];
+        for entry in self.entries.iter().filter(|e| e.ip_address.to_canonical() == ip_address) {
+            for hostname in &entry.hostnames {
+                if !hostnames.iter().any(|h| h.eq_ignore_ascii_case(hostname)) {
+                    hostnames.push(hostname.clone());
+                }
+            }
+        }
+        hostnames
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Nameserver {
+    pub ip_address: IpAddress,
+    // IPv6 zone index, e.g. `eth0` in `fe80::1%eth0`
+    pub zone: Option<String>,
+}
+
+impl Display for Nameserver {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        match &self.zone {
+            Some(zone) => write!(f, "{}%{}", self.ip_address, zone),
+            None => write!(f, "{}", self.ip_address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvOptions {
+    pub ndots: u8,
+    // seconds
+    pub timeout: u32,
+    pub attempts: u32,
+    pub rotate: bool,
+    // options not listed above, e.g. `edns0`, `single-request`
+    pub others: Vec<String>,
+}
+
+impl Default for ResolvOptions {
+    fn default() -> Self {
+        Self { ndots: 1, timeout: 5, attempts: 2, rotate: false, others: vec![] }
+    }
+}
+
+/// Resolver config like `/etc/resolv.conf`, lines start with `#` or `;` are comments
+#[derive(Debug, Clone, Default)]
+pub struct ResolvConf {
+    // all nameservers in file order, see `get_nameservers` for the ones actually used
+    pub nameservers: Vec<Nameserver>,
+    pub domain: Option<String>,
+    pub search: Vec<String>,
+    pub options: ResolvOptions,
+    // invalid lines are skipped and recorded here
+    pub errors: Vec<IpListEntryError>,
+}
+
+impl ResolvConf {
+    pub fn load() -> XResult<Self> {
+        Self::load_file(DEFAULT_RESOLV_CONF_FILE)
+    }
+
+    pub fn load_file<P: AsRef<Path>>(path: P) -> XResult<Self> {
+        let path = path.as_ref();
+        let file = opt_result!(File::open(path), "Open resolv.conf: {}, failed: {}", path.display());
+        Self::load_reader(BufReader::new(file), &path.display().to_string())
+    }
+
+    pub fn load_reader<R: BufRead>(reader: R, name: &str) -> XResult<Self> {
+        let mut resolv_conf = Self::default();
+        for (line_index, line) in reader.lines().enumerate() {
+            let line = opt_result!(line, "Read resolv.conf: {}, failed: {}", name);
+            let entry = line.trim();
+            if entry.is_empty() || entry.starts_with('#') || entry.starts_with(';') {
+                continue;
+            }
+            let mut errors = vec![];
+            let mut fields = entry.split_whitespace();
+            let keyword = fields.next().unwrap_or("");
+            let values = fields.collect::<Vec<_>>();
+            match keyword {
+                "nameserver" => match values.first().and_then(|v| parse_ip_address(v)) {
+                    Some((ip_address, zone)) if values.len() == 1 => resolv_conf.nameservers.push(Nameserver { ip_address, zone }),
+                    _ => errors.push("Invalid nameserver".to_string()),
+                },
+                // `domain` and `search` are mutually exclusive, the last one wins
+                "domain" => match values.as_slice() {
+                    [domain] if is_valid_hostname(domain) => {
+                        resolv_conf.domain = Some(domain.to_string());
+                        resolv_conf.search.clear();
+                    }
+                    _ => errors.push("Invalid domain".to_string()),
+                },
+                "search" => {
+                    if values.is_empty() {
+                        errors.push("Missing search domain".to_string());
+                    } else if let Some(domain) = values.iter().find(|d| !is_valid_hostname(d)) {
+                        errors.push(format!("Invalid search domain: {}", domain));
+                    } else {
+                        resolv_conf.search = values.iter().map(ToString::to_string).collect();
+                        resolv_conf.domain = None;
+                    }
+                }
+                "options" => for option in &values {
+                    if let Err(message) = resolv_conf.options.parse_option(option) {
+                        errors.push(message);
+                    }
+                },
+                // sortlist is rarely used and not supported
+                "sortlist" => {}
+                _ => errors.push(format!("Unknown keyword: {}", keyword)),
+            }
+            resolv_conf.errors.extend(errors.into_iter().map(|message| IpListEntryError {
+                file: name.to_string(),
+                line: line_index + 1,
+                entry: entry.to_string(),
+                message,
+            }));
+        }
+        Ok(resolv_conf)
+    }
+
+    pub fn ensure_no_errors(self) -> XResult<Self> {
+        if self.errors.is_empty() {
+            return Ok(self);
+        }
+        simple_error!("Load resolv.conf with {} invalid lines: {}", self.errors.len(),
+            self.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>().join("; "))
+    }
+
+    /// Nameservers used by resolver, at most `MAX_NAMESERVERS`
+    pub fn get_nameservers(&self) -> &[Nameserver] {
+        &self.nameservers[..self.nameservers.len().min(MAX_NAMESERVERS)]
+    }
+
+    /// Search list, `domain` is used when there is no `search`
+    pub fn get_search_domains(&self) -> Vec<String> {
+        match &self.domain {
+            Some(domain) if self.search.is_empty() => vec![domain.clone()],
+            _ => self.search.clone(),
+        }
+    }
+}
+
+impl ResolvOptions {
+    // values exceed the limits are capped like glibc does
+    fn parse_option(&mut self, option: &str) -> Result<(), String> {
+        let parse_value = |value: &str, max: u32| value.parse::<u32>().map(|v| v.min(max))
+            .map_err(|_| format!("Invalid option value: {}", option));
+        match option.split_once(':') {
+            Some(("ndots", value)) => self.ndots = parse_value(value, 15)? as u8,
+            Some(("timeout", value)) => self.timeout = parse_value(value, 30)?,
+            Some(("attempts", value)) => self.attempts = parse_value(value, 5)?,
+            None if option == "rotate" => self.rotate = true,
+            _ => self.others.push(option.to_string()),
+        }
+        Ok(())
+    }
+}
+
+// address with optional zone index, e.g. `fe80::1%eth0`, zone index is only allowed for IPv6
+fn parse_ip_address(ip_address: &str) -> Option<(IpAddress, Option<String>)> {
+    match ip_address.split_once('%') {
+        None => IpAddress::parse(ip_address).map(|ip_address| (ip_address, None)),
+        Some((_, "")) => None,
+        Some((ip_address, zone)) => match IpAddress::parse(ip_address)? {
+            ip_address @ IpAddress::Ipv6(_) => Some((ip_address, Some(zone.to_string()))),
+            IpAddress::Ipv4(_) => None,
+        },
+    }
+}
+
+#[test]
+fn test_hosts_file() {
+    let hosts = r#"
+# comment
+127.0.0.1   localhost localhost.localdomain
+::1         localhost ip6-localhost   # inline comment
+192.168.1.10 Server.example.com server
+192.168.1.11 server.example.com.
+fe80::1%lo0  link-local
+fe80::1%eth0 link-local
+fe80::1%lo0  link-local-alias link-local
+10.0.0.1
+not-an-ip   host
+10.0.0.2    bad_host
+10.0.0.3%lo0 bad-zone
+"#;
+    let hosts_file = HostsFile::load_reader(hosts.as_bytes(), "hosts").unwrap();
+    assert_eq!(7, hosts_file.entries.len());
+    assert_eq!(vec!["hosts:10: Missing hostname, entry: 10.0.0.1", "hosts:11: Invalid IP address, entry: not-an-ip   host",
+                    "hosts:12: Invalid hostname: bad_host, entry: 10.0.0.2    bad_host",
+                    "hosts:13: Invalid IP address, entry: 10.0.0.3%lo0 bad-zone"],
+               hosts_file.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>());
+    let format_ips = |ips: Vec<(IpAddress, Option<String>)>| ips.iter().map(|(ip, zone)| match zone {
+        Some(zone) => format!("{}%{}", ip, zone),
+        None => ip.to_string(),
+    }).collect::<Vec<_>>();
+    assert_eq!(vec!["127.0.0.1", "::1"], format_ips(hosts_file.lookup("LOCALHOST")));
+    assert_eq!(vec!["192.168.1.10", "192.168.1.11"], format_ips(hosts_file.lookup("server.example.com.")));
+    assert_eq!(vec!["fe80::1%lo0", "fe80::1%eth0"], format_ips(hosts_file.lookup("link-local")));
+    assert_eq!(Some("lo0".to_string()), hosts_file.entries[4].zone);
+    assert_eq!(None, hosts_file.entries[0].zone);
+    assert!(hosts_file.lookup("unknown").is_empty());
+    assert_eq!(vec!["Server.example.com", "server"], hosts_file.reverse_lookup(&IpAddress::Ipv4([192, 168, 1, 10])));
+    assert_eq!(vec!["localhost", "localhost.localdomain"], hosts_file.reverse_lookup(&IpAddress::parse("::ffff:127.0.0.1").unwrap()));
+    assert!(hosts_file.reverse_lookup(&IpAddress::Ipv4([10, 0, 0, 1])).is_empty());
+    assert!(hosts_file.ensure_no_errors().is_err());
+    assert!(HostsFile::load_file("/not-exists/hosts").is_err());
+}
+
+#[test]
+fn test_resolv_conf() {
+    let resolv_conf = r#"
+# generated by provisioning
+; another comment
+nameserver 10.0.0.2
+nameserver fe80::1%eth0
+nameserver 8.8.8.8
+nameserver 8.8.4.4
+nameserver bad
+nameserver fe80::2%
+domain example.com
+search corp.example.com example.com
+options ndots:5 timeout:60 rotate edns0 attempts:x
+sortlist 130.155.160.0/255.255.240.0
+unknown value
+"#;
+    let resolv_conf = ResolvConf::load_reader(resolv_conf.as_bytes(), "resolv.conf").unwrap();
+    assert_eq!(4, resolv_conf.nameservers.len());
+    assert_eq!(vec!["10.0.0.2", "fe80::1%eth0", "8.8.8.8"], resolv_conf.get_nameservers().iter().map(|n| n.to_string()).collect::<Vec<_>>());
+    assert_eq!(Nameserver { ip_address: IpAddress::parse("fe80::1").unwrap(), zone: Some("eth0".to_string()) }, resolv_conf.nameservers[1]);
+    assert_eq!(None, resolv_conf.domain);
+    assert_eq!(vec!["corp.example.com", "example.com"], resolv_conf.get_search_domains());
+    assert_eq!(ResolvOptions { ndots: 5, timeout: 30, attempts: 2, rotate: true, others: vec!["edns0".to_string()] }, resolv_conf.options);
+    assert_eq!(vec!["resolv.conf:8: Invalid nameserver, entry: nameserver bad",
+                    "resolv.conf:9: Invalid nameserver, entry: nameserver fe80::2%",
+                    "resolv.conf:12: Invalid option value: attempts:x, entry: options ndots:5 timeout:60 rotate edns0 attempts:x",
+                    "resolv.conf:14: Unknown keyword: unknown, entry: unknown value"],
+               resolv_conf.errors.iter().map(|e| e.to_string()).collect::<Vec<_>>());
+
+    let resolv_conf = ResolvConf::load_reader("search a.example.com\ndomain b.example.com\n".as_bytes(), "resolv.conf").unwrap();
+    assert_eq!(vec!["b.example.com"], resolv_conf.get_search_domains());
+    let resolv_conf = resolv_conf.ensure_no_errors().unwrap();
+    assert_eq!(ResolvOptions::default(), resolv_conf.options);
+    assert!(resolv_conf.get_nameservers().is_empty());
+}
@@ -0,0 +1,206 @@
+use crate::util_net::{IpAddress, IpAddressMask, IpAddressMaskGroup};
+
+// IANA IPv4 and IPv6 special-purpose address registries
+lazy_static! {
+    static ref PRIVATE_NETWORKS: Vec<IpAddressMask> = parse_networks(&[
+        "10.0.0.0/8", "172.16.0.0/12", "192.168.0.0/16", // RFC 1918
+        "fc00::/7", // RFC 4193 unique local address
+    ]);
+    static ref LOOPBACK_NETWORKS: Vec<IpAddressMask> = parse_networks(&["127.0.0.0/8", "::1/128"]);
+    static ref LINK_LOCAL_NETWORKS: Vec<IpAddressMask> = parse_networks(&["169.254.0.0/16", "fe80::/10"]);
+    static ref MULTICAST_NETWORKS: Vec<IpAddressMask> = parse_networks(&["224.0.0.0/4", "ff00::/8"]);
+    static ref DOCUMENTATION_NETWORKS: Vec<IpAddressMask> = parse_networks(&[
+        "192.0.2.0/24", "198.51.100.0/24", "203.0.113.0/24", // RFC 5737
+        "2001:db8::/32", "3fff::/20", // RFC 3849, RFC 9637
+    ]);
+    static ref CGNAT_NETWORKS: Vec<IpAddressMask> = parse_networks(&["100.64.0.0/10"]);
+    static ref BENCHMARKING_NETWORKS: Vec<IpAddressMask> = parse_networks(&["198.18.0.0/15", "2001:2::/48"]);
+    static ref RESERVED_NETWORKS: Vec<IpAddressMask> = parse_networks(&[
+        "0.0.0.0/8", // this network
+        "192.0.0.0/24", // IETF protocol assignments
+        "192.88.99.0/24", // deprecated 6to4 relay anycast
+        "240.0.0.0/4", // reserved, includes limited broadcast
+        "::/128", // unspecified
+        "64:ff9b:1::/48", // local-use IPv4/IPv6 translation
+        "100::/64", // discard-only
+        "2001::/23", // IETF protocol assignments
+        "5f00::/16", // SRv6 SIDs, RFC 9602
+    ]);
+    // globally reachable addresses inside the special-purpose blocks above
+    static ref GLOBAL_EXCEPTIONS: Vec<IpAddressMask> = parse_networks(&[
+        "192.0.0.9/32", "192.0.0.10/32", // PCP anycast, TURN anycast
+        "2001:1::1/128", "2001:1::2/128", // PCP anycast, TURN anycast
+        "2001:3::/32", // AMT
+        "2001:4:112::/48", // AS112-v6
+        "2001:20::/28", "2001:30::/28", // ORCHIDv2, drone remote ID
+    ]);
+    static ref NAT64_NETWORK: IpAddressMask = parse_networks(&["64:ff9b::/96"]).remove(0);
+    static ref SIX_TO_FOUR_NETWORK: IpAddressMask = parse_networks(&["2002::/16"]).remove(0);
+    static ref GLOBAL_UNICAST_NETWORKS: Vec<IpAddressMask> = parse_networks(&["2000::/3"]);
+}
+
+/// Special-purpose address classification, IPv4-mapped IPv6 addresses are classified as IPv4
+impl IpAddress {
+    // RFC 1918 or IPv6 unique local address
+    pub fn is_private(&self) -> bool {
+        is_in_networks(&PRIVATE_NETWORKS, self)
+    }
+
+    pub fn is_loopback(&self) -> bool {
+        is_in_networks(&LOOPBACK_NETWORKS, self)
+    }
+
+    pub fn is_link_local(&self) -> bool {
+        is_in_networks(&LINK_LOCAL_NETWORKS, self)
+    }
+
+    pub fn is_multicast(&self) -> bool {
+        is_in_networks(&MULTICAST_NETWORKS, self)
+    }
+
+    pub fn is_documentation(&self) -> bool {
+        is_in_networks(&DOCUMENTATION_NETWORKS, self)
+    }
+
+    // RFC 6598 shared address space
+    pub fn is_cgnat(&self) -> bool {
+        is_in_networks(&CGNAT_NETWORKS, self)
+    }
+
+    pub fn is_benchmarking(&self) -> bool {
+        is_in_networks(&BENCHMARKING_NETWORKS, self)
+    }
+
+    pub fn is_unspecified(&self) -> bool {
+        match self.to_canonical() {
+            IpAddress::Ipv4(ipv4) => ipv4 == [0; 4],
+            IpAddress::Ipv6(ipv6) => ipv6 == [0; 16],
+        }
+    }
+
+    pub fn is_reserved(&self) -> bool {
+        is_in_networks(&RESERVED_NETWORKS, self)
+    }
+
+    /// IPv4 address embedded in NAT64 `64:ff9b::/96` or 6to4 `2002::/16` address
+    pub fn get_embedded_ipv4(&self) -> Option<IpAddress> {
+        let ip_address = self.to_canonical();
+        match &ip_address {
+            IpAddress::Ipv6(ipv6) if NAT64_NETWORK.contains_ip_address(&ip_address) => {
+                Some(IpAddress::Ipv4([ipv6[12], ipv6[13], ipv6[14], ipv6[15]]))
+            }
+            IpAddress::Ipv6(ipv6) if SIX_TO_FOUR_NETWORK.contains_ip_address(&ip_address) => {
+                Some(IpAddress::Ipv4([ipv6[2], ipv6[3], ipv6[4], ipv6[5]]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Globally routable unicast address, i.e. not in any special-purpose block above except the globally reachable
+    /// ones listed in the registries (e.g. PCP and TURN anycast), IPv6 address must also be in `2000::/3`,
+    /// NAT64 and 6to4 addresses are classified by the embedded IPv4 address
+    pub fn is_global(&self) -> bool {
+        if let Some(ipv4) = self.get_embedded_ipv4() {
+            return ipv4.is_global();
+        }
+        let ip_address = self.to_canonical();
+        if is_in_networks(&GLOBAL_EXCEPTIONS, &ip_address) {
+            return true;
+        }
+        let is_special = ip_address.is_private() || ip_address.is_loopback() || ip_address.is_link_local()
+            || ip_address.is_multicast() || ip_address.is_documentation() || ip_address.is_cgnat()
+            || ip_address.is_benchmarking() || ip_address.is_reserved();
+        !is_special && (ip_address.is_ipv4() || is_in_networks(&GLOBAL_UNICAST_NETWORKS, &ip_address))
+    }
+}
+
+/// RFC 1918 networks and IPv6 unique local address `fc00::/7`
+pub fn get_private_networks() -> IpAddressMaskGroup {
+    IpAddressMaskGroup { ip_address_mask_group: PRIVATE_NETWORKS.clone() }
+}
+
+/// All non globally routable networks: private, loopback, link-local, multicast,
+/// documentation, CGNAT, benchmarking and reserved, e.g. for SSRF protection,
+/// NAT64 and 6to4 addresses which embed these IPv4 addresses are not covered, and globally reachable exceptions
+/// inside these networks (e.g. `192.0.0.9/32`) are included, check `IpAddress::is_global` instead
+pub fn get_special_purpose_networks() -> IpAddressMaskGroup {
+    let networks: [&Vec<IpAddressMask>; 8] = [&PRIVATE_NETWORKS, &LOOPBACK_NETWORKS, &LINK_LOCAL_NETWORKS, &MULTICAST_NETWORKS,
+        &DOCUMENTATION_NETWORKS, &CGNAT_NETWORKS, &BENCHMARKING_NETWORKS, &RESERVED_NETWORKS];
+    IpAddressMaskGroup { ip_address_mask_group: networks.iter().flat_map(|n| n.iter().cloned()).collect() }
+}
+
+fn is_in_networks(networks: &[IpAddressMask], ip_address: &IpAddress) -> bool {
+    let ip_address = ip_address.to_canonical();
+    networks.iter().any(|network| network.contains_ip_address(&ip_address))
+}
+
+fn parse_networks(networks: &[&str]) -> Vec<IpAddressMask> {
+    networks.iter().map(|n| IpAddressMask::parse(n).unwrap_or_else(|| panic!("Invalid network: {}", n))).collect()
+}
+
+#[test]
+fn test_ipv4_classification() {
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    assert!(ip("10.1.2.3").is_private());
+    assert!(ip("172.31.255.255").is_private());
+    assert!(!ip("172.32.0.1").is_private());
+    assert!(ip("::ffff:192.168.1.1").is_private());
+    assert!(ip("127.0.0.1").is_loopback());
+    assert!(ip("169.254.169.254").is_link_local());
+    assert!(ip("239.255.255.250").is_multicast());
+    assert!(ip("203.0.113.7").is_documentation());
+    assert!(ip("100.100.1.1").is_cgnat());
+    assert!(ip("198.19.0.1").is_benchmarking());
+    assert!(ip("0.0.0.0").is_unspecified());
+    assert!(ip("255.255.255.255").is_reserved());
+    assert!(ip("8.8.8.8").is_global());
+    for not_global in ["10.0.0.1", "127.0.0.1", "169.254.1.1", "224.0.0.1", "192.0.2.1",
+        "100.64.0.1", "198.18.0.1", "0.1.2.3", "240.0.0.1", "192.0.0.8", "::ffff:127.0.0.1"] {
+        assert!(!ip(not_global).is_global(), "{}", not_global);
+    }
+    for global in ["192.0.0.9", "192.0.0.10", "::ffff:192.0.0.9"] {
+        assert!(ip(global).is_reserved(), "{}", global);
+        assert!(ip(global).is_global(), "{}", global);
+    }
+}
+
+#[test]
+fn test_ipv6_classification() {
+    let ip = |s: &str| IpAddress::parse(s).unwrap();
+    assert!(ip("fd12:3456::1").is_private());
+    assert!(ip("::1").is_loopback());
+    assert!(ip("fe80::1").is_link_local());
+    assert!(ip("ff02::1").is_multicast());
+    assert!(ip("2001:db8::1").is_documentation());
+    assert!(ip("2001:2::1").is_benchmarking());
+    assert!(ip("::").is_unspecified());
+    assert!(ip("100::1").is_reserved());
+    assert!(ip("2606:4700::1111").is_global());
+    assert_eq!(Some(ip("127.0.0.1")), ip("64:ff9b::7f00:1").get_embedded_ipv4());
+    assert_eq!(Some(ip("192.168.1.1")), ip("2002:c0a8:0101::").get_embedded_ipv4());
+    assert_eq!(None, ip("64:ff9b:1::7f00:1").get_embedded_ipv4());
+    for not_global in ["64:ff9b::7f00:1", "64:ff9b::10.0.0.1", "64:ff9b::169.254.169.254", "2002:c0a8:0101::", "2002:7f00:1::1", "2002:a9fe:a9fe::"] {
+        assert!(!ip(not_global).is_global(), "{}", not_global);
+    }
+    assert!(ip("64:ff9b::8.8.8.8").is_global());
+    assert!(ip("2002:0808:0808::1").is_global());
+    for not_global in ["::", "::1", "fc00::1", "fe80::1", "ff0e::1", "2001:db8::1", "2001::1", "3fff::1", "4000::1",
+        "5f00::1", "2001:1::3", "2001:4:113::1", "2001:10::1"] {
+        assert!(!ip(not_global).is_global(), "{}", not_global);
+    }
+    assert!(ip("5f00::1").is_reserved());
+    for global in ["2001:1::1", "2001:1::2", "2001:3::1", "2001:4:112::1", "2001:20::1", "2001:2f:ffff::1", "2001:30::1"] {
+        assert!(ip(global).is_reserved(), "{}", global);
+        assert!(ip(global).is_global(), "{}", global);
+    }
+}
+
+#[test]
+fn test_private_networks() {
+    let private_networks = get_private_networks();
+    assert_eq!("[10.0.0.0/8, 172.16.0.0/12, 192.168.0.0/16, fc00::/7]", private_networks.to_string());
+    assert!(private_networks.is_matches(&"192.168.1.1:80".parse().unwrap()));
+    let special_purpose_networks = get_special_purpose_networks();
+    assert!(special_purpose_networks.contains_ip_address(&IpAddress::parse("169.254.169.254").unwrap()));
+    assert!(!special_purpose_networks.contains_ip_address(&IpAddress::parse("1.1.1.1").unwrap()));
+}
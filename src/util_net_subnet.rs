@@ -0,0 +1,204 @@
+use crate::iff;
+use crate::util_net::{ipv4_to_u32, ipv6_mask, ipv6_to_u128, value_to_ip_address, value_to_ip_address_mask, IpAddress, IpAddressMask};
+
+/// Subnet calculator, IPv4 `/31` and `/32` have no network or broadcast address reserved (RFC 3021),
+/// all IPv6 addresses are usable hosts
+impl IpAddressMask {
+    pub fn network(&self) -> IpAddress {
+        let (bits, value, mask) = self.to_bits_value_mask();
+        value_to_ip_address(bits == 32, value & mask)
+    }
+
+    // the last address in subnet, for IPv6 there is no broadcast address but it is still the last address
+    pub fn broadcast(&self) -> IpAddress {
+        let (bits, value, mask) = self.to_bits_value_mask();
+        value_to_ip_address(bits == 32, (value & mask) | (!mask & max_value(bits)))
+    }
+
+    // e.g. 255.255.255.0 for /24
+    pub fn netmask(&self) -> IpAddress {
+        let (bits, _, mask) = self.to_bits_value_mask();
+        value_to_ip_address(bits == 32, mask)
+    }
+
+    // e.g. 0.0.0.255 for /24
+    pub fn wildcard_mask(&self) -> IpAddress {
+        let (bits, _, mask) = self.to_bits_value_mask();
+        value_to_ip_address(bits == 32, !mask & max_value(bits))
+    }
+
+    // same prefix with host bits cleared, e.g. 10.0.0.1/24 -> 10.0.0.0/24
+    pub fn to_network_mask(&self) -> Self {
+        let (bits, value, mask) = self.to_bits_value_mask();
+        value_to_ip_address_mask(bits == 32, value & mask, self.mask_len())
+    }
+
+    pub fn first_host(&self) -> IpAddress {
+        let (first, _) = self.get_host_range();
+        value_to_ip_address(self.bits() == 32, first)
+    }
+
+    pub fn last_host(&self) -> IpAddress {
+        let (_, last) = self.get_host_range();
+        value_to_ip_address(self.bits() == 32, last)
+    }
+
+    /// Usable host count, saturated to `u128::MAX` for IPv6 `/0`
+    pub fn host_count(&self) -> u128 {
+        let (first, last) = self.get_host_range();
+        (last - first).saturating_add(1)
+    }
+
+    /// Iterate over usable hosts
+    pub fn hosts(&self) -> IpAddressMaskHosts {
+        let (first, last) = self.get_host_range();
+        IpAddressMaskHosts { bits: self.bits(), next: Some(first), last }
+    }
+
+    /// Split into subnets with `mask_len`, returns `None` when `mask_len` is shorter than current mask or too long
+    pub fn subnets(&self, mask_len: u8) -> Option<IpAddressMaskSubnets> {
+        let bits = self.bits();
+        if mask_len < self.mask_len() || mask_len as u32 > bits {
+            return None;
+        }
+        let (_, value, mask) = self.to_bits_value_mask();
+        let step_shift = bits - mask_len as u32;
+        let last = (value & mask) | (!mask & max_value(bits));
+        Some(IpAddressMaskSubnets { bits, mask_len, step_shift, next: Some(value & mask), last })
+    }
+
+    /// The smallest prefix which contains both, returns `None` when address families are different
+    pub fn supernet(&self, other: &Self) -> Option<Self> {
+        let (bits, value, _) = self.to_bits_value_mask();
+        let (other_bits, other_value, _) = other.to_bits_value_mask();
+        if bits != other_bits {
+            return None;
+        }
+        // move to 128 bits so leading_zeros works for both IPv4 and IPv6
+        let shift = 128 - bits;
+        let common_len = ((value << shift) ^ (other_value << shift)).leading_zeros().min(bits) as u8;
+        let mask_len = common_len.min(self.mask_len()).min(other.mask_len());
+        Some(value_to_ip_address_mask(bits == 32, value, mask_len).to_network_mask())
+    }
+
+    fn bits(&self) -> u32 {
+        iff!(matches!(self, IpAddressMask::Ipv4(_, _)), 32, 128)
+    }
+
+    // (bits, address value, mask value), IPv4 values are in the low 32 bits
+    fn to_bits_value_mask(&self) -> (u32, u128, u128) {
+        match self {
+            IpAddressMask::Ipv4(ipv4, mask_len) => (32, ipv4_to_u32(ipv4) as u128, ipv6_mask(*mask_len) >> 96),
+            IpAddressMask::Ipv6(ipv6, mask_len) => (128, ipv6_to_u128(ipv6), ipv6_mask(*mask_len)),
+        }
+    }
+
+    // (first, last) usable host values
+    fn get_host_range(&self) -> (u128, u128) {
+        let (bits, value, mask) = self.to_bits_value_mask();
+        let network = value & mask;
+        let broadcast = network | (!mask & max_value(bits));
+        iff!(bits == 32 && self.mask_len() <= 30, (network + 1, broadcast - 1), (network, broadcast))
+    }
+}
+
+pub struct IpAddressMaskHosts {
+    bits: u32,
+    next: Option<u128>,
+    last: u128,
+}
+
+impl Iterator for IpAddressMaskHosts {
+    type Item = IpAddress;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        self.next = iff!(current >= self.last, None, Some(current + 1));
+        Some(value_to_ip_address(self.bits == 32, current))
+    }
+}
+
+pub struct IpAddressMaskSubnets {
+    bits: u32,
+    mask_len: u8,
+    step_shift: u32,
+    next: Option<u128>,
+    last: u128,
+}
+
+impl Iterator for IpAddressMaskSubnets {
+    type Item = IpAddressMask;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.next?;
+        // step_shift is 128 only when splitting IPv6 /0 into /0
+        let subnet_last = iff!(self.step_shift >= 128, u128::MAX, current | ((1_u128 << self.step_shift) - 1));
+        self.next = iff!(subnet_last >= self.last, None, Some(subnet_last + 1));
+        Some(value_to_ip_address_mask(self.bits == 32, current, self.mask_len))
+    }
+}
+
+fn max_value(bits: u32) -> u128 {
+    iff!(bits == 32, u32::MAX as u128, u128::MAX)
+}
+
+#[test]
+fn test_ipv4_subnet() {
+    let mask = IpAddressMask::parse("192.168.1.77/26").unwrap();
+    assert_eq!("192.168.1.64", mask.network().to_string());
+    assert_eq!("192.168.1.127", mask.broadcast().to_string());
+    assert_eq!("255.255.255.192", mask.netmask().to_string());
+    assert_eq!("0.0.0.63", mask.wildcard_mask().to_string());
+    assert_eq!("192.168.1.64/26", mask.to_network_mask().to_string());
+    assert_eq!("192.168.1.65", mask.first_host().to_string());
+    assert_eq!("192.168.1.126", mask.last_host().to_string());
+    assert_eq!(62, mask.host_count());
+    assert_eq!(62, mask.hosts().count());
+
+    let mask = IpAddressMask::parse("10.0.0.0/31").unwrap();
+    assert_eq!(2, mask.host_count());
+    assert_eq!(vec!["10.0.0.0", "10.0.0.1"], mask.hosts().map(|h| h.to_string()).collect::<Vec<_>>());
+    let mask = IpAddressMask::parse("10.0.0.1/32").unwrap();
+    assert_eq!(1, mask.host_count());
+    assert_eq!("10.0.0.1", mask.first_host().to_string());
+    let mask = IpAddressMask::parse("0.0.0.0/0").unwrap();
+    assert_eq!("255.255.255.255", mask.broadcast().to_string());
+    assert_eq!("0.0.0.0", mask.netmask().to_string());
+    assert_eq!((1_u128 << 32) - 2, mask.host_count());
+}
+
+#[test]
+fn test_ipv6_subnet() {
+    let mask = IpAddressMask::parse("2001:db8::1/64").unwrap();
+    assert_eq!("2001:db8::", mask.network().to_string());
+    assert_eq!("2001:db8::ffff:ffff:ffff:ffff", mask.broadcast().to_string());
+    assert_eq!("ffff:ffff:ffff:ffff::", mask.netmask().to_string());
+    assert_eq!("2001:db8::", mask.first_host().to_string());
+    assert_eq!(1_u128 << 64, mask.host_count());
+    assert_eq!(u128::MAX, IpAddressMask::parse("::/0").unwrap().host_count());
+    assert_eq!(vec!["2001:db8::", "2001:db8::1"],
+               IpAddressMask::parse("2001:db8::/127").unwrap().hosts().map(|h| h.to_string()).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_subnets_and_supernet() {
+    let mask = IpAddressMask::parse("10.0.0.0/24").unwrap();
+    let subnets = mask.subnets(26).unwrap().map(|m| m.to_string()).collect::<Vec<_>>();
+    assert_eq!(vec!["10.0.0.0/26", "10.0.0.64/26", "10.0.0.128/26", "10.0.0.192/26"], subnets);
+    assert_eq!(1, mask.subnets(24).unwrap().count());
+    assert_eq!(256, mask.subnets(32).unwrap().count());
+    assert!(mask.subnets(23).is_none());
+    assert!(mask.subnets(33).is_none());
+    assert_eq!("2001:db8:0:1::/64",
+               IpAddressMask::parse("2001:db8::/48").unwrap().subnets(64).unwrap().nth(1).unwrap().to_string());
+    assert_eq!(1, IpAddressMask::parse("::/0").unwrap().subnets(0).unwrap().count());
+
+    let supernet = |a: &str, b: &str| IpAddressMask::parse(a).unwrap()
+        .supernet(&IpAddressMask::parse(b).unwrap()).map(|m| m.to_string());
+    assert_eq!(Some("10.0.0.0/23".to_string()), supernet("10.0.0.0/24", "10.0.1.0/24"));
+    assert_eq!(Some("10.0.0.0/8".to_string()), supernet("10.0.0.0/8", "10.1.0.0/16"));
+    assert_eq!(Some("0.0.0.0/0".to_string()), supernet("10.0.0.0/8", "192.168.0.0/16"));
+    assert_eq!(Some("1.2.3.4/32".to_string()), supernet("1.2.3.4", "1.2.3.4"));
+    assert_eq!(Some("2001:db8::/32".to_string()), supernet("2001:db8::/48", "2001:db8:ff00::/48"));
+    assert_eq!(None, supernet("10.0.0.0/8", "2001:db8::/32"));
+}
@@ -0,0 +1,136 @@
+use std::any::Any;
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::env;
+use std::fs;
+use std::panic;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use crate::{iff, util_err, util_msg, util_runtime, util_time};
+
+pub const DEFAULT_PANIC_EXIT_CODE: i32 = 101;
+
+static IN_PANIC_HOOK: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone)]
+pub struct PanicHookConfig {
+    pub exit_code: i32,
+    pub force_backtrace: bool,
+    // `None` means do not write crash report
+    pub crash_report_dir: Option<PathBuf>,
+}
+
+impl Default for PanicHookConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PanicHookConfig {
+    pub fn new() -> Self {
+        Self {
+            exit_code: DEFAULT_PANIC_EXIT_CODE,
+            force_backtrace: false,
+            crash_report_dir: Some(env::temp_dir()),
+        }
+    }
+
+    pub fn with_exit_code(mut self, exit_code: i32) -> Self {
+        self.exit_code = exit_code;
+        self
+    }
+
+    /// Capture backtrace even when env `RUST_BACKTRACE` is not set
+    pub fn with_force_backtrace(mut self, force_backtrace: bool) -> Self {
+        self.force_backtrace = force_backtrace;
+        self
+    }
+
+    pub fn with_crash_report_dir(mut self, crash_report_dir: Option<PathBuf>) -> Self {
+        self.crash_report_dir = crash_report_dir;
+        self
+    }
+}
+
+/// Install panic hook: print panic by `failure!`, write crash report, invoke exit callbacks and exit
+pub fn install_panic_hook(config: PanicHookConfig) {
+    panic::set_hook(Box::new(move |panic_info| {
+        // panic in exit callbacks, exit immediately
+        if IN_PANIC_HOOK.swap(true, Ordering::SeqCst) {
+            process::exit(config.exit_code);
+        }
+        let message = get_panic_message(panic_info.payload());
+        let location = panic_info.location().map(|l| format!("{}:{}", l.file(), l.line()));
+        let thread_name = thread::current().name().unwrap_or("<unnamed>").to_string();
+        let backtrace = if config.force_backtrace { Backtrace::force_capture() } else { Backtrace::capture() };
+        let backtrace = iff!(backtrace.status() == BacktraceStatus::Captured, Some(backtrace.to_string()), None);
+
+        util_err::print_failure(&format!("Thread '{}' panicked at {}: {}",
+                                         thread_name, location.as_deref().unwrap_or("<unknown>"), message),
+                                 panic_info.location().map(|l| (l.file(), l.line())), config.exit_code);
+        if let Some(backtrace) = &backtrace {
+            if !util_err::is_error_json() {
+                util_msg::print_error(&format!("Backtrace:\n{}", backtrace));
+            }
+        }
+        if let Some(crash_report_dir) = &config.crash_report_dir {
+            let crash_report = make_crash_report(&message, location.as_deref(), &thread_name, backtrace.as_deref());
+            let crash_report_file = crash_report_dir.join(format!("crash-report-{}-{}.txt", process::id(), util_time::get_current_millis()));
+            match fs::write(&crash_report_file, crash_report) {
+                Ok(_) => util_msg::print_error(&format!("Crash report written to: {}", crash_report_file.display())),
+                Err(e) => util_msg::print_error(&format!("Write crash report: {}, failed: {}", crash_report_file.display(), e)),
+            }
+        }
+        if !util_runtime::try_invoke_callbacks() {
+            util_msg::print_error("Exit callbacks are running, skip invoke exit callbacks");
+        }
+        process::exit(config.exit_code);
+    }));
+}
+
+pub fn get_panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "Box<dyn Any>".to_string()
+    }
+}
+
+fn make_crash_report(message: &str, location: Option<&str>, thread_name: &str, backtrace: Option<&str>) -> String {
+    let mut report = String::with_capacity(1024);
+    report.push_str(&format!("Message: {}\n", message));
+    report.push_str(&format!("Location: {}\n", location.unwrap_or("<unknown>")));
+    report.push_str(&format!("Thread: {}\n", thread_name));
+    report.push_str(&format!("Time(millis): {}\n", util_time::get_current_millis()));
+    report.push_str(&format!("Pid: {}\n", process::id()));
+    report.push_str(&format!("OS: {}, Arch: {}\n", env::consts::OS, env::consts::ARCH));
+    report.push_str(&format!("Executable: {}\n",
+                             env::current_exe().map(|p| p.display().to_string()).unwrap_or_else(|_| "<unknown>".to_string())));
+    report.push_str(&format!("Arguments: {:?}\n", env::args().collect::<Vec<_>>()));
+    report.push_str(&format!("Work dir: {}\n", crate::util_os::get_full_work_dir().unwrap_or_else(|| "<unknown>".to_string())));
+    if let Some(backtrace) = backtrace {
+        report.push_str(&format!("Backtrace:\n{}\n", backtrace));
+    }
+    report
+}
+
+#[test]
+fn test_get_panic_message() {
+    let payload: Box<dyn Any + Send> = Box::new("test panic");
+    assert_eq!("test panic", get_panic_message(payload.as_ref()));
+    let payload: Box<dyn Any + Send> = Box::new("test panic".to_string());
+    assert_eq!("test panic", get_panic_message(payload.as_ref()));
+    let payload: Box<dyn Any + Send> = Box::new(1);
+    assert_eq!("Box<dyn Any>", get_panic_message(payload.as_ref()));
+}
+
+#[test]
+fn test_make_crash_report() {
+    let report = make_crash_report("test panic", Some("src/main.rs:1"), "main", None);
+    assert!(report.starts_with("Message: test panic\nLocation: src/main.rs:1\nThread: main\n"));
+    assert!(!report.contains("Backtrace:"));
+}
@@ -0,0 +1,413 @@
+use std::io::{Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use crate::{iff, opt_result, simple_error, XResult};
+
+pub const PROXY_PROTOCOL_V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+const PROXY_PROTOCOL_V1_PREFIX: &[u8] = b"PROXY ";
+// `PROXY TCP6 ffff:...:ffff ffff:...:ffff 65535 65535\r\n`
+const PROXY_PROTOCOL_V1_MAX_LEN: usize = 107;
+const PROXY_PROTOCOL_V2_HEADER_LEN: usize = 16;
+
+pub const PP2_TYPE_ALPN: u8 = 0x01;
+pub const PP2_TYPE_AUTHORITY: u8 = 0x02;
+pub const PP2_TYPE_CRC32C: u8 = 0x03;
+pub const PP2_TYPE_NOOP: u8 = 0x04;
+pub const PP2_TYPE_UNIQUE_ID: u8 = 0x05;
+pub const PP2_TYPE_SSL: u8 = 0x20;
+pub const PP2_TYPE_NETNS: u8 = 0x30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyProtocolVersion {
+    V1,
+    V2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyCommand {
+    // health check from proxy itself, addresses should be ignored
+    Local,
+    Proxy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyTransport {
+    Unspecified,
+    Stream,
+    Datagram,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyTlv {
+    pub r#type: u8,
+    pub value: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyHeader {
+    pub version: ProxyProtocolVersion,
+    pub command: ProxyCommand,
+    pub transport: ProxyTransport,
+    // `None` for v1 `UNKNOWN`, v2 `LOCAL`, unspecified or unix socket addresses
+    pub source: Option<SocketAddr>,
+    pub destination: Option<SocketAddr>,
+    // v2 only
+    pub tlvs: Vec<ProxyTlv>,
+}
+
+impl ProxyHeader {
+    pub fn new_v1(source: SocketAddr, destination: SocketAddr) -> Self {
+        Self::new(ProxyProtocolVersion::V1, source, destination)
+    }
+
+    pub fn new_v2(source: SocketAddr, destination: SocketAddr) -> Self {
+        Self::new(ProxyProtocolVersion::V2, source, destination)
+    }
+
+    // v2 `LOCAL` command, e.g. for health check
+    pub fn new_v2_local() -> Self {
+        Self {
+            version: ProxyProtocolVersion::V2,
+            command: ProxyCommand::Local,
+            transport: ProxyTransport::Unspecified,
+            source: None,
+            destination: None,
+            tlvs: vec![],
+        }
+    }
+
+    fn new(version: ProxyProtocolVersion, source: SocketAddr, destination: SocketAddr) -> Self {
+        Self {
+            version,
+            command: ProxyCommand::Proxy,
+            transport: ProxyTransport::Stream,
+            source: Some(source),
+            destination: Some(destination),
+            tlvs: vec![],
+        }
+    }
+
+    pub fn with_tlv(mut self, r#type: u8, value: Vec<u8>) -> Self {
+        self.tlvs.push(ProxyTlv { r#type, value });
+        self
+    }
+
+    pub fn get_tlv(&self, r#type: u8) -> Option<&ProxyTlv> {
+        self.tlvs.iter().find(|tlv| tlv.r#type == r#type)
+    }
+
+    /// Original client address, or `peer_addr` when there is no source address (e.g. `LOCAL` command)
+    pub fn get_client_addr(&self, peer_addr: SocketAddr) -> SocketAddr {
+        self.source.unwrap_or(peer_addr)
+    }
+
+    /// Read header from stream, reads exactly the header bytes and nothing more,
+    /// v1 header is read byte by byte, so wrap unbuffered stream with `BufReader` is recommended
+    pub fn read<R: Read>(mut r: R) -> XResult<Self> {
+        // v1 header is at least 15 bytes (`PROXY UNKNOWN\r\n`), v2 header is at least 16 bytes
+        let mut buf = vec![0_u8; PROXY_PROTOCOL_V2_SIGNATURE.len()];
+        opt_result!(r.read_exact(&mut buf), "Read PROXY protocol header failed: {}");
+        if buf == PROXY_PROTOCOL_V2_SIGNATURE {
+            buf.resize(PROXY_PROTOCOL_V2_HEADER_LEN, 0);
+            opt_result!(r.read_exact(&mut buf[PROXY_PROTOCOL_V2_SIGNATURE.len()..]), "Read PROXY protocol v2 header failed: {}");
+            let len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+            buf.resize(PROXY_PROTOCOL_V2_HEADER_LEN + len, 0);
+            opt_result!(r.read_exact(&mut buf[PROXY_PROTOCOL_V2_HEADER_LEN..]), "Read PROXY protocol v2 addresses failed: {}");
+        } else if buf.starts_with(PROXY_PROTOCOL_V1_PREFIX) {
+            let mut byte = [0_u8; 1];
+            while !buf.ends_with(b"\r\n") {
+                if buf.len() >= PROXY_PROTOCOL_V1_MAX_LEN {
+                    return simple_error!("PROXY protocol v1 header too long");
+                }
+                opt_result!(r.read_exact(&mut byte), "Read PROXY protocol v1 header failed: {}");
+                buf.push(byte[0]);
+            }
+        } else {
+            return simple_error!("No PROXY protocol header found");
+        }
+        match Self::parse(&buf)? {
+            Some((header, _)) => Ok(header),
+            None => simple_error!("Incomplete PROXY protocol header"),
+        }
+    }
+
+    /// Parse header from buffer, returns `Ok(None)` when more bytes are needed,
+    /// or the header and its length in bytes
+    pub fn parse(buf: &[u8]) -> XResult<Option<(Self, usize)>> {
+        if buf.starts_with(&PROXY_PROTOCOL_V2_SIGNATURE) {
+            return parse_v2(buf);
+        }
+        if buf.starts_with(PROXY_PROTOCOL_V1_PREFIX) {
+            return parse_v1(buf);
+        }
+        if PROXY_PROTOCOL_V2_SIGNATURE.starts_with(buf) || PROXY_PROTOCOL_V1_PREFIX.starts_with(buf) {
+            return Ok(None);
+        }
+        simple_error!("No PROXY protocol header found")
+    }
+
+    pub fn compose(&self) -> XResult<Vec<u8>> {
+        match self.version {
+            ProxyProtocolVersion::V1 => self.compose_v1(),
+            ProxyProtocolVersion::V2 => self.compose_v2(),
+        }
+    }
+
+    pub fn write<W: Write>(&self, mut w: W) -> XResult<usize> {
+        let header = self.compose()?;
+        opt_result!(w.write_all(&header), "Write PROXY protocol header failed: {}");
+        Ok(header.len())
+    }
+
+    fn compose_v1(&self) -> XResult<Vec<u8>> {
+        if !self.tlvs.is_empty() {
+            return simple_error!("PROXY protocol v1 does not support TLVs");
+        }
+        let line = match (self.command, get_same_family_addrs(self.source, self.destination)) {
+            (ProxyCommand::Proxy, Some((source, destination))) => format!("PROXY {} {} {} {} {}\r\n",
+                iff!(source.is_ipv4(), "TCP4", "TCP6"), source.ip(), destination.ip(), source.port(), destination.port()),
+            _ => "PROXY UNKNOWN\r\n".to_string(),
+        };
+        Ok(line.into_bytes())
+    }
+
+    fn compose_v2(&self) -> XResult<Vec<u8>> {
+        let mut body = vec![];
+        let addrs = iff!(self.command == ProxyCommand::Proxy, get_same_family_addrs(self.source, self.destination), None);
+        let family = match addrs {
+            Some((SocketAddr::V4(source), SocketAddr::V4(destination))) => {
+                body.extend_from_slice(&source.ip().octets());
+                body.extend_from_slice(&destination.ip().octets());
+                body.extend_from_slice(&source.port().to_be_bytes());
+                body.extend_from_slice(&destination.port().to_be_bytes());
+                0x10
+            }
+            Some((source, destination)) => {
+                body.extend_from_slice(&to_ipv6(source.ip()).octets());
+                body.extend_from_slice(&to_ipv6(destination.ip()).octets());
+                body.extend_from_slice(&source.port().to_be_bytes());
+                body.extend_from_slice(&destination.port().to_be_bytes());
+                0x20
+            }
+            None => 0x00,
+        };
+        let transport = match (family, self.transport) {
+            (0x00, _) | (_, ProxyTransport::Unspecified) => 0x00,
+            (_, ProxyTransport::Stream) => 0x01,
+            (_, ProxyTransport::Datagram) => 0x02,
+        };
+        for tlv in &self.tlvs {
+            if tlv.value.len() > u16::MAX as usize {
+                return simple_error!("PROXY protocol TLV: {} too long: {}", tlv.r#type, tlv.value.len());
+            }
+            body.push(tlv.r#type);
+            body.extend_from_slice(&(tlv.value.len() as u16).to_be_bytes());
+            body.extend_from_slice(&tlv.value);
+        }
+        if body.len() > u16::MAX as usize {
+            return simple_error!("PROXY protocol header too long: {}", body.len());
+        }
+        let mut header = Vec::with_capacity(PROXY_PROTOCOL_V2_HEADER_LEN + body.len());
+        header.extend_from_slice(&PROXY_PROTOCOL_V2_SIGNATURE);
+        header.push(0x20 | iff!(self.command == ProxyCommand::Proxy, 0x01, 0x00));
+        header.push(family | transport);
+        header.extend_from_slice(&(body.len() as u16).to_be_bytes());
+        header.extend_from_slice(&body);
+        Ok(header)
+    }
+}
+
+fn parse_v1(buf: &[u8]) -> XResult<Option<(ProxyHeader, usize)>> {
+    let line_end = match buf.windows(2).take(PROXY_PROTOCOL_V1_MAX_LEN - 1).position(|w| w == b"\r\n") {
+        Some(line_end) => line_end,
+        None if buf.len() >= PROXY_PROTOCOL_V1_MAX_LEN => return simple_error!("PROXY protocol v1 header too long"),
+        None => return Ok(None),
+    };
+    let line = opt_result!(std::str::from_utf8(&buf[..line_end]), "Invalid PROXY protocol v1 header: {}");
+    let parts = line.split(' ').collect::<Vec<_>>();
+    let (source, destination) = match (parts.get(1).copied(), parts.len()) {
+        (Some("UNKNOWN"), _) => (None, None),
+        (Some(family @ ("TCP4" | "TCP6")), 6) => {
+            let parse_addr = |ip: &str, port: &str| -> Option<SocketAddr> {
+                let ip = ip.parse::<IpAddr>().ok().filter(|ip| ip.is_ipv4() == (family == "TCP4"))?;
+                // port must be decimal without leading zeros
+                let port = port.parse::<u16>().ok().filter(|p| p.to_string() == port)?;
+                Some(SocketAddr::new(ip, port))
+            };
+            match (parse_addr(parts[2], parts[4]), parse_addr(parts[3], parts[5])) {
+                (Some(source), Some(destination)) => (Some(source), Some(destination)),
+                _ => return simple_error!("Invalid PROXY protocol v1 header: {}", line),
+            }
+        }
+        _ => return simple_error!("Invalid PROXY protocol v1 header: {}", line),
+    };
+    let header = ProxyHeader {
+        version: ProxyProtocolVersion::V1,
+        command: ProxyCommand::Proxy,
+        transport: iff!(source.is_some(), ProxyTransport::Stream, ProxyTransport::Unspecified),
+        source,
+        destination,
+        tlvs: vec![],
+    };
+    Ok(Some((header, line_end + 2)))
+}
+
+fn parse_v2(buf: &[u8]) -> XResult<Option<(ProxyHeader, usize)>> {
+    if buf.len() < PROXY_PROTOCOL_V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let total_len = PROXY_PROTOCOL_V2_HEADER_LEN + u16::from_be_bytes([buf[14], buf[15]]) as usize;
+    if buf.len() < total_len {
+        return Ok(None);
+    }
+    let body = &buf[PROXY_PROTOCOL_V2_HEADER_LEN..total_len];
+    if buf[12] >> 4 != 2 {
+        return simple_error!("Invalid PROXY protocol v2 version: {}", buf[12] >> 4);
+    }
+    let command = match buf[12] & 0x0f {
+        0x00 => ProxyCommand::Local,
+        0x01 => ProxyCommand::Proxy,
+        command => return simple_error!("Invalid PROXY protocol v2 command: {}", command),
+    };
+    let transport = match buf[13] & 0x0f {
+        0x00 => ProxyTransport::Unspecified,
+        0x01 => ProxyTransport::Stream,
+        0x02 => ProxyTransport::Datagram,
+        transport => return simple_error!("Invalid PROXY protocol v2 transport: {}", transport),
+    };
+    let addrs_len = match buf[13] >> 4 {
+        0x00 => 0,
+        0x01 => 12,
+        0x02 => 36,
+        0x03 => 216,
+        family => return simple_error!("Invalid PROXY protocol v2 address family: {}", family),
+    };
+    if body.len() < addrs_len {
+        return simple_error!("PROXY protocol v2 addresses too short: {}", body.len());
+    }
+    let port_at = |i: usize| u16::from_be_bytes([body[i], body[i + 1]]);
+    let addrs = match addrs_len {
+        12 => {
+            let ip_at = |i: usize| IpAddr::V4(Ipv4Addr::new(body[i], body[i + 1], body[i + 2], body[i + 3]));
+            Some((SocketAddr::new(ip_at(0), port_at(8)), SocketAddr::new(ip_at(4), port_at(10))))
+        }
+        36 => {
+            let ip_at = |i: usize| {
+                let mut octets = [0_u8; 16];
+                octets.copy_from_slice(&body[i..i + 16]);
+                IpAddr::V6(Ipv6Addr::from(octets))
+            };
+            Some((SocketAddr::new(ip_at(0), port_at(32)), SocketAddr::new(ip_at(16), port_at(34))))
+        }
+        // unix socket address is not supported by `SocketAddr`
+        _ => None,
+    };
+    let addrs = iff!(command == ProxyCommand::Proxy, addrs, None);
+    let mut tlvs = vec![];
+    let mut tlv_bytes = &body[addrs_len..];
+    while !tlv_bytes.is_empty() {
+        if tlv_bytes.len() < 3 {
+            return simple_error!("Invalid PROXY protocol v2 TLV");
+        }
+        let len = u16::from_be_bytes([tlv_bytes[1], tlv_bytes[2]]) as usize;
+        if tlv_bytes.len() < 3 + len {
+            return simple_error!("PROXY protocol v2 TLV: {} too short", tlv_bytes[0]);
+        }
+        tlvs.push(ProxyTlv { r#type: tlv_bytes[0], value: tlv_bytes[3..3 + len].to_vec() });
+        tlv_bytes = &tlv_bytes[3 + len..];
+    }
+    let header = ProxyHeader {
+        version: ProxyProtocolVersion::V2,
+        command,
+        transport,
+        source: addrs.map(|(source, _)| source),
+        destination: addrs.map(|(_, destination)| destination),
+        tlvs,
+    };
+    Ok(Some((header, total_len)))
+}
+
+// both addresses in the same family, IPv4 address is mapped to IPv6 when families are different
+fn get_same_family_addrs(source: Option<SocketAddr>, destination: Option<SocketAddr>) -> Option<(SocketAddr, SocketAddr)> {
+    let (source, destination) = (source?, destination?);
+    if source.is_ipv4() == destination.is_ipv4() {
+        return Some((source, destination));
+    }
+    let to_ipv6_addr = |addr: SocketAddr| SocketAddr::new(IpAddr::V6(to_ipv6(addr.ip())), addr.port());
+    Some((to_ipv6_addr(source), to_ipv6_addr(destination)))
+}
+
+fn to_ipv6(ip: IpAddr) -> Ipv6Addr {
+    match ip {
+        IpAddr::V4(ipv4) => ipv4.to_ipv6_mapped(),
+        IpAddr::V6(ipv6) => ipv6,
+    }
+}
+
+#[test]
+fn test_proxy_protocol_v1() {
+    let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+    let mut stream = std::io::Cursor::new(b"PROXY TCP4 192.168.1.1 10.0.0.1 56324 443\r\nGET /".to_vec());
+    let header = ProxyHeader::read(&mut stream).unwrap();
+    assert_eq!(ProxyProtocolVersion::V1, header.version);
+    assert_eq!(Some(addr("192.168.1.1:56324")), header.source);
+    assert_eq!(Some(addr("10.0.0.1:443")), header.destination);
+    assert_eq!(43, stream.position());
+    assert_eq!(b"PROXY TCP4 192.168.1.1 10.0.0.1 56324 443\r\n".to_vec(), header.compose().unwrap());
+
+    let header = ProxyHeader::new_v1(addr("[2001:db8::1]:1000"), addr("[::1]:80"));
+    let bytes = header.compose().unwrap();
+    assert_eq!(b"PROXY TCP6 2001:db8::1 ::1 1000 80\r\n".to_vec(), bytes);
+    assert_eq!(Some((header, bytes.len())), ProxyHeader::parse(&bytes).unwrap());
+
+    let (header, len) = ProxyHeader::parse(b"PROXY UNKNOWN ffff::1 ::1 1 2\r\n").unwrap().unwrap();
+    assert_eq!((None, 31), (header.source, len));
+    assert_eq!(addr("1.2.3.4:5"), header.get_client_addr(addr("1.2.3.4:5")));
+    assert_eq!(None, ProxyHeader::parse(b"PROXY TCP4 1.2.3.4").unwrap());
+    assert_eq!(None, ProxyHeader::parse(b"PRO").unwrap());
+    assert!(ProxyHeader::parse(b"PROXY TCP4 ::1 ::1 1 2\r\n").is_err());
+    assert!(ProxyHeader::parse(b"PROXY TCP4 1.1.1.1 1.1.1.1 01 2\r\n").is_err());
+    assert!(ProxyHeader::parse(b"GET / HTTP/1.1\r\n").is_err());
+    assert!(ProxyHeader::read(&[b'P'; 200][..]).is_err());
+    assert!(ProxyHeader::read(&b"PROXY ".repeat(30)[..]).is_err());
+    assert!(ProxyHeader::new_v1(addr("1.1.1.1:1"), addr("2.2.2.2:2")).with_tlv(PP2_TYPE_NOOP, vec![]).compose().is_err());
+}
+
+#[test]
+fn test_proxy_protocol_v2() {
+    let addr = |s: &str| s.parse::<SocketAddr>().unwrap();
+    let header = ProxyHeader::new_v2(addr("192.168.1.1:56324"), addr("10.0.0.1:443"))
+        .with_tlv(PP2_TYPE_AUTHORITY, b"example.com".to_vec());
+    let bytes = header.compose().unwrap();
+    assert_eq!(PROXY_PROTOCOL_V2_SIGNATURE, bytes[..12]);
+    assert_eq!([0x21, 0x11, 0, 12 + 3 + 11], bytes[12..16]);
+    assert_eq!([192, 168, 1, 1, 10, 0, 0, 1, 0xdc, 0x04, 0x01, 0xbb], bytes[16..28]);
+
+    let mut stream_bytes = bytes.clone();
+    stream_bytes.extend_from_slice(b"payload");
+    let mut stream = std::io::Cursor::new(stream_bytes);
+    let read_header = ProxyHeader::read(&mut stream).unwrap();
+    assert_eq!(header, read_header);
+    assert_eq!(b"example.com".to_vec(), read_header.get_tlv(PP2_TYPE_AUTHORITY).unwrap().value);
+    assert_eq!(bytes.len() as u64, stream.position());
+    assert_eq!(None, ProxyHeader::parse(&bytes[..bytes.len() - 1]).unwrap());
+    assert_eq!(None, ProxyHeader::parse(&bytes[..10]).unwrap());
+
+    // mixed families are sent as IPv6
+    let header = ProxyHeader::new_v2(addr("1.2.3.4:1"), addr("[::1]:2"));
+    let (parsed_header, _) = ProxyHeader::parse(&header.compose().unwrap()).unwrap().unwrap();
+    assert_eq!(Some(addr("[::ffff:1.2.3.4]:1")), parsed_header.source);
+
+    let local = ProxyHeader::new_v2_local();
+    let bytes = local.compose().unwrap();
+    assert_eq!([0x20, 0x00, 0, 0], bytes[12..16]);
+    assert_eq!(Some((local, 16)), ProxyHeader::parse(&bytes).unwrap());
+
+    let mut invalid = bytes.clone();
+    invalid[12] = 0x31;
+    assert!(ProxyHeader::parse(&invalid).is_err());
+    let mut invalid = ProxyHeader::new_v2(addr("1.1.1.1:1"), addr("2.2.2.2:2")).compose().unwrap();
+    invalid.extend_from_slice(&[PP2_TYPE_NOOP, 0, 5]);
+    invalid[15] += 3;
+    assert!(ProxyHeader::parse(&invalid).is_err());
+}
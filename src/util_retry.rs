@@ -0,0 +1,213 @@
+use std::collections::hash_map::RandomState;
+use std::error::Error;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, ErrorKind};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{iff, util_exit, util_msg, util_time, XResult};
+
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backoff {
+    Fixed(Duration),
+    Exponential { initial: Duration, max: Duration },
+    // exponential with equal jitter, delay is random in [delay / 2, delay]
+    Jittered { initial: Duration, max: Duration },
+}
+
+impl Backoff {
+    /// Parse backoff, durations are parsed by `util_time::parse_duration`:
+    /// `1s` -> fixed, `100..30s` -> exponential, `~100..30s` -> jittered exponential
+    pub fn parse(backoff: &str) -> Option<Self> {
+        let backoff = backoff.trim();
+        let (is_jittered, backoff) = match backoff.strip_prefix('~') {
+            Some(b) => (true, b),
+            None => (false, backoff),
+        };
+        let parts = backoff.split("..").collect::<Vec<_>>();
+        match (parts.len(), is_jittered) {
+            (1, false) => util_time::parse_duration(parts[0]).map(Backoff::Fixed),
+            (2, _) => {
+                let initial = util_time::parse_duration(parts[0])?;
+                let max = util_time::parse_duration(parts[1])?;
+                Some(iff!(is_jittered, Backoff::Jittered { initial, max }, Backoff::Exponential { initial, max }))
+            }
+            _ => None,
+        }
+    }
+
+    /// Delay after the `attempt`th (starts from 1) failure
+    pub fn get_delay(&self, attempt: u32) -> Duration {
+        match self {
+            Backoff::Fixed(interval) => *interval,
+            Backoff::Exponential { initial, max } => get_exponential_delay(*initial, *max, attempt),
+            Backoff::Jittered { initial, max } => {
+                let delay = get_exponential_delay(*initial, *max, attempt).as_millis() as u64;
+                let half_delay = delay / 2;
+                let jitter = random_u64() % (delay - half_delay + 1);
+                Duration::from_millis(half_delay + jitter)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    pub backoff: Backoff,
+    pub max_attempts: Option<u32>,
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self::new(Backoff::Fixed(Duration::from_secs(1)))
+    }
+}
+
+impl RetryConfig {
+    pub fn new(backoff: Backoff) -> Self {
+        Self { backoff, max_attempts: Some(DEFAULT_MAX_ATTEMPTS), deadline: None }
+    }
+
+    /// e.g. `RetryConfig::parse("~500..30s", 5, Some("2m"))`
+    pub fn parse(backoff: &str, max_attempts: u32, deadline: Option<&str>) -> Option<Self> {
+        let mut config = Self::new(Backoff::parse(backoff)?).with_max_attempts(max_attempts);
+        if let Some(deadline) = deadline {
+            config = config.with_deadline(util_time::parse_duration(deadline)?);
+        }
+        Some(config)
+    }
+
+    /// 0 means unlimited attempts
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = iff!(max_attempts == 0, None, Some(max_attempts));
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+}
+
+/// Retry on any error
+pub fn retry<T, F>(config: &RetryConfig, f: F) -> XResult<T> where F: FnMut() -> XResult<T> {
+    retry_if(config, |_| true, f)
+}
+
+/// Retry when `is_retryable` returns true, returns the last error when attempts or deadline exceeded
+pub fn retry_if<T, F, P>(config: &RetryConfig, is_retryable: P, mut f: F) -> XResult<T>
+    where F: FnMut() -> XResult<T>,
+          P: Fn(&(dyn Error + 'static)) -> bool {
+    let start = Instant::now();
+    let mut attempt = 1_u32;
+    loop {
+        let e = match f() {
+            Ok(o) => return Ok(o),
+            Err(e) => e,
+        };
+        if !is_retryable(e.as_ref()) {
+            return Err(e);
+        }
+        if let Some(max_attempts) = config.max_attempts {
+            if attempt >= max_attempts {
+                util_msg::print_warn(&format!("Attempt {} of {} failed: {}, give up", attempt, max_attempts, e));
+                return Err(e);
+            }
+        }
+        let delay = config.backoff.get_delay(attempt);
+        if let Some(deadline) = config.deadline {
+            if start.elapsed() + delay > deadline {
+                util_msg::print_warn(&format!("Attempt {} failed: {}, deadline {}ms exceeded, give up", attempt, e, deadline.as_millis()));
+                return Err(e);
+            }
+        }
+        util_msg::print_warn(&format!("Attempt {} failed: {}, retry in {}ms", attempt, e, delay.as_millis()));
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+/// Transient errors: network io errors, or exit code is `EXIT_TEMP_FAIL` or `EXIT_UNAVAILABLE`
+pub fn is_transient_error(error: &(dyn Error + 'static)) -> bool {
+    let mut current = Some(error);
+    while let Some(e) = current {
+        if let Some(io_error) = e.downcast_ref::<io::Error>() {
+            return is_transient_io_error_kind(io_error.kind());
+        }
+        current = e.source();
+    }
+    let exit_code = util_exit::get_exit_code(error);
+    exit_code == util_exit::EXIT_TEMP_FAIL || exit_code == util_exit::EXIT_UNAVAILABLE
+}
+
+fn is_transient_io_error_kind(kind: ErrorKind) -> bool {
+    matches!(kind, ErrorKind::ConnectionRefused | ErrorKind::ConnectionReset | ErrorKind::ConnectionAborted
+        | ErrorKind::NotConnected | ErrorKind::BrokenPipe | ErrorKind::TimedOut
+        | ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::UnexpectedEof)
+}
+
+fn get_exponential_delay(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    let factor = 2_f64.powi(attempt.saturating_sub(1).min(62) as i32);
+    let delay_millis = initial.as_millis() as f64 * factor;
+    iff!(delay_millis >= max.as_millis() as f64, max, Duration::from_millis(delay_millis as u64))
+}
+
+fn random_u64() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[test]
+fn test_backoff_parse() {
+    assert_eq!(Some(Backoff::Fixed(Duration::from_secs(1))), Backoff::parse("1s"));
+    assert_eq!(Some(Backoff::Exponential { initial: Duration::from_millis(100), max: Duration::from_secs(30) }), Backoff::parse("100..30s"));
+    assert_eq!(Some(Backoff::Jittered { initial: Duration::from_millis(100), max: Duration::from_secs(60) }), Backoff::parse("~100..1m"));
+    assert_eq!(None, Backoff::parse("~1s"));
+    assert_eq!(None, Backoff::parse("x..1s"));
+    assert_eq!(None, Backoff::parse("1s..2s..3s"));
+}
+
+#[test]
+fn test_backoff_get_delay() {
+    let backoff = Backoff::parse("100..1s").unwrap();
+    assert_eq!(Duration::from_millis(100), backoff.get_delay(1));
+    assert_eq!(Duration::from_millis(200), backoff.get_delay(2));
+    assert_eq!(Duration::from_millis(800), backoff.get_delay(4));
+    assert_eq!(Duration::from_secs(1), backoff.get_delay(5));
+    assert_eq!(Duration::from_secs(1), backoff.get_delay(100));
+    let backoff = Backoff::parse("~100..1s").unwrap();
+    for attempt in 1..10 {
+        let delay = backoff.get_delay(attempt);
+        let exponential_delay = get_exponential_delay(Duration::from_millis(100), Duration::from_secs(1), attempt);
+        assert!(delay >= exponential_delay / 2 && delay <= exponential_delay);
+    }
+}
+
+#[test]
+fn test_retry() {
+    let config = RetryConfig::parse("0", 3, None).unwrap();
+    let mut count = 0;
+    let r: XResult<()> = retry(&config, || {
+        count += 1;
+        Err(crate::SimpleError::new("failed".into()).into())
+    });
+    assert!(r.is_err());
+    assert_eq!(3, count);
+
+    let mut count = 0;
+    let r = retry(&config, || {
+        count += 1;
+        iff!(count < 2, Err(io::Error::new(ErrorKind::TimedOut, "timeout").into()), Ok(count))
+    });
+    assert_eq!(2, r.unwrap());
+
+    let mut count = 0;
+    let r: XResult<()> = retry_if(&config, is_transient_error, || {
+        count += 1;
+        Err(io::Error::new(ErrorKind::NotFound, "not found").into())
+    });
+    assert!(r.is_err());
+    assert_eq!(1, count);
+}
@@ -1,4 +1,4 @@
-use std::sync::Mutex;
+use std::sync::{Mutex, TryLockError};
 use crate::util_msg::MessageType;
 
 lazy_static! {
@@ -12,9 +12,24 @@ pub fn register_callback<F>(f: F) where F: Fn() + Send + 'static {
 
 pub fn invoke_callbacks() {
     let mut exit_callbacks = EXIT_CALLBACK.lock().unwrap();
+    run_callbacks(&mut exit_callbacks);
+}
+
+/// Invoke callbacks unless they are running (e.g. called from panic hook), returns false when skipped
+pub fn try_invoke_callbacks() -> bool {
+    let mut exit_callbacks = match EXIT_CALLBACK.try_lock() {
+        Ok(exit_callbacks) => exit_callbacks,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return false,
+    };
+    run_callbacks(&mut exit_callbacks);
+    true
+}
+
+fn run_callbacks(exit_callbacks: &mut Vec<Box<dyn Fn() + Send + 'static>>) {
     let total = exit_callbacks.len();
     let mut index = 0;
-    while exit_callbacks.len() > 0 {
+    while !exit_callbacks.is_empty() {
         crate::util_msg::when(MessageType::DEBUG, || {
             crate::util_msg::print_debug(&format!("Running exit callbacks: {} of {}", index, total));
         });